@@ -1,3 +1,11 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, Write},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
 fn parse_cargo(cargo: &[&str]) -> Vec<Vec<char>> {
     let cargo_lines: Vec<Vec<Option<char>>> = cargo
         .iter()
@@ -13,19 +21,42 @@ fn parse_cargo(cargo: &[&str]) -> Vec<Vec<char>> {
     let max_cargo_size = cargo_lines.iter().map(|c| c.len()).max().unwrap();
     let mut cargos: Vec<Vec<char>> = vec![vec![]; max_cargo_size];
 
-    for cargo_line in cargo_lines {
+    for cargo_line in cargo_lines.iter().rev() {
         for (index, value) in cargo_line.iter().enumerate() {
-            let mut cargo_n = cargos.get(index).cloned().unwrap_or(vec![]);
             if let Some(v) = value {
-                cargo_n.insert(0, *v);
+                cargos[index].push(*v);
             }
-            cargos[index] = cargo_n;
         }
     }
 
     cargos
 }
 
+fn render_cargo(cargos: &[Vec<char>]) -> String {
+    let max_height = cargos.iter().map(|c| c.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = vec![];
+
+    for level in (0..max_height).rev() {
+        let line = cargos
+            .iter()
+            .map(|c| match c.get(level) {
+                Some(v) => format!("[{v}]"),
+                None => "   ".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(line);
+    }
+
+    let footer = (1..=cargos.len())
+        .map(|i| format!("{i:^3}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    lines.push(footer);
+
+    lines.join("\n")
+}
+
 #[derive(Debug, Clone)]
 struct Move {
     length: usize,
@@ -48,14 +79,36 @@ fn parse_moves(moves: &[&str]) -> Vec<Move> {
     target
 }
 
+impl Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.length, self.source, self.target
+        )
+    }
+}
+
+fn take_package(cargos: &mut [Vec<char>], m: &Move) -> Vec<char> {
+    let source = &mut cargos[m.source - 1];
+    source.split_off(source.len().saturating_sub(m.length))
+}
+
+fn apply_single_move(cargos: &mut [Vec<char>], m: &Move) -> Vec<char> {
+    if m.source == m.target {
+        // Moving crates one by one onto the same stack leaves it unchanged
+        let source = &cargos[m.source - 1];
+        return source[source.len().saturating_sub(m.length)..].to_vec();
+    }
+
+    let package = take_package(cargos, m);
+    cargos[m.target - 1].extend(package.iter().rev());
+    package
+}
+
 fn apply_move(mut cargos: Vec<Vec<char>>, moves: Vec<Move>) -> Vec<Vec<char>> {
     for m in moves {
-        for _ in 0..m.length {
-            let el = cargos[m.source - 1].pop();
-            if let Some(e) = el {
-                cargos[m.target - 1].push(e);
-            }
-        }
+        apply_single_move(&mut cargos, &m);
     }
 
     cargos
@@ -81,20 +134,15 @@ fn part1() {
             .collect::<String>()
     );
 }
+fn apply_single_move_2(cargos: &mut [Vec<char>], m: &Move) -> Vec<char> {
+    let package = take_package(cargos, m);
+    cargos[m.target - 1].extend_from_slice(&package);
+    package
+}
+
 fn apply_move_2(mut cargos: Vec<Vec<char>>, moves: Vec<Move>) -> Vec<Vec<char>> {
     for m in moves {
-        let source = &mut cargos[m.source - 1];
-        let mut package: Vec<char> = vec![];
-
-        for _ in 0..m.length {
-            if let Some(el) = source.pop() {
-                package.insert(0, el);
-            }
-        }
-
-        for e in package {
-            cargos[m.target - 1].push(e);
-        }
+        apply_single_move_2(&mut cargos, &m);
     }
 
     cargos
@@ -121,7 +169,686 @@ fn part2() {
     );
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Crane {
+    CrateMover9000,
+    CrateMover9001,
+}
+
+impl Crane {
+    /// Applies the move and returns the crates taken from the source stack, bottom first
+    fn apply(&self, cargos: &mut [Vec<char>], m: &Move) -> Vec<char> {
+        match self {
+            Crane::CrateMover9000 => apply_single_move(cargos, m),
+            Crane::CrateMover9001 => apply_single_move_2(cargos, m),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AppliedMove {
+    source: usize,
+    target: usize,
+    crates: Vec<char>,
+}
+
+impl AppliedMove {
+    fn undo(&self, cargos: &mut [Vec<char>]) {
+        let target = &mut cargos[self.target - 1];
+        target.truncate(target.len() - self.crates.len());
+        cargos[self.source - 1].extend_from_slice(&self.crates);
+    }
+}
+
+const SNAPSHOT_INTERVAL: usize = 100;
+
+/// Invertible record of a procedure : every applied move is kept so any prefix can be undone,
+/// redone or jumped to without replaying from the initial state
+#[derive(Debug)]
+struct MoveLog {
+    crane: Crane,
+    moves: Vec<Move>,
+    cargos: Vec<Vec<char>>,
+    history: Vec<AppliedMove>,
+    position: usize,
+    snapshots: Vec<Vec<Vec<char>>>,
+}
+
+impl MoveLog {
+    fn new(crane: Crane, cargos: Vec<Vec<char>>, moves: Vec<Move>) -> Self {
+        Self {
+            crane,
+            moves,
+            snapshots: vec![cargos.clone()],
+            cargos,
+            history: vec![],
+            position: 0,
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        let Some(m) = self.moves.get(self.position) else {
+            return false;
+        };
+
+        let crates = self.crane.apply(&mut self.cargos, m);
+        if self.position == self.history.len() {
+            self.history.push(AppliedMove {
+                source: m.source,
+                target: m.target,
+                crates,
+            });
+        }
+        self.position += 1;
+
+        if self.position == self.snapshots.len() * SNAPSHOT_INTERVAL {
+            self.snapshots.push(self.cargos.clone());
+        }
+        true
+    }
+
+    fn undo(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+
+        self.position -= 1;
+        self.history[self.position].undo(&mut self.cargos);
+        true
+    }
+
+    /// Moves to the state right after the `position`-th move, restarting from the closest
+    /// snapshot when it is nearer than the current state
+    fn seek(&mut self, position: usize) {
+        let position = position.min(self.moves.len());
+        let snapshot = (position / SNAPSHOT_INTERVAL).min(self.snapshots.len() - 1);
+        let snapshot_position = snapshot * SNAPSHOT_INTERVAL;
+
+        if self.position.abs_diff(position) > position - snapshot_position {
+            self.cargos = self.snapshots[snapshot].clone();
+            self.position = snapshot_position;
+        }
+
+        while self.position < position && self.redo() {}
+        while self.position > position && self.undo() {}
+    }
+
+    fn tops(&self) -> String {
+        self.cargos
+            .iter()
+            .map(|c| c.last().copied().unwrap_or(' '))
+            .collect()
+    }
+}
+
+struct Frame {
+    title: String,
+    drawing: String,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.title)?;
+        writeln!(f)?;
+        writeln!(f, "{}", self.drawing)
+    }
+}
+
+struct Options {
+    animate: bool,
+    bench: bool,
+    every: usize,
+    crane: Crane,
+    export: Option<String>,
+    interactive: bool,
+    at: Vec<usize>,
+}
+
+impl Options {
+    fn from_args(args: &[String]) -> Self {
+        let mut options = Self {
+            animate: false,
+            bench: false,
+            every: 1,
+            crane: Crane::CrateMover9000,
+            export: None,
+            interactive: false,
+            at: vec![],
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--animate" => {
+                    options.animate = true;
+                    options.interactive = true;
+                }
+                "--every" => {
+                    options.every = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|n| *n > 0)
+                        .expect("--every expects a positive number");
+                }
+                "--crane" => {
+                    options.crane = match args.next().map(|c| c.as_str()) {
+                        Some("9000") => Crane::CrateMover9000,
+                        Some("9001") => Crane::CrateMover9001,
+                        c => panic!("--crane expects 9000 or 9001 but found {c:?}"),
+                    };
+                }
+                "--bench" => options.bench = true,
+                "--at" => {
+                    options.at.push(
+                        args.next()
+                            .and_then(|n| n.parse().ok())
+                            .expect("--at expects a move number"),
+                    );
+                }
+                "--export" => {
+                    options.animate = true;
+                    options.export = Some(args.next().expect("--export expects a path").clone());
+                }
+                _ => panic!("Unexpected argument '{arg}'"),
+            }
+        }
+
+        options
+    }
+}
+
+fn build_frames(log: &mut MoveLog, every: usize) -> Vec<Frame> {
+    let total = log.moves.len();
+    log.seek(0);
+
+    let mut frames = vec![Frame {
+        title: format!("step 0/{total} : initial state"),
+        drawing: render_cargo(&log.cargos),
+    }];
+
+    while log.redo() {
+        let step = log.position;
+        if step.is_multiple_of(every) || step == total {
+            frames.push(Frame {
+                title: format!("step {step}/{total} : {}", log.moves[step - 1]),
+                drawing: render_cargo(&log.cargos),
+            });
+        }
+    }
+
+    frames
+}
+
+fn export_frames(frames: &[Frame], path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    for frame in frames {
+        writeln!(file, "{frame}")?;
+    }
+    Ok(())
+}
+
+const PLAY_DELAY: Duration = Duration::from_millis(200);
+
+fn show_frame(frames: &[Frame], index: usize, playing: bool) {
+    print!("\x1b[2J\x1b[H{}", frames[index]);
+    println!();
+    if playing {
+        println!("playing... press enter to pause");
+    } else {
+        println!("[enter/n] next  [b] back  [p] play  [g N] go to frame  [r] rewind  [q] quit");
+    }
+    std::io::stdout().flush().ok();
+}
+
+fn run_controller(frames: &[Frame]) {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let last = frames.len() - 1;
+    let mut index = 0;
+    let mut playing = false;
+
+    loop {
+        show_frame(frames, index, playing);
+
+        let command = if playing {
+            match rx.recv_timeout(PLAY_DELAY) {
+                Ok(_) => {
+                    playing = false;
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if index == last {
+                        playing = false;
+                    } else {
+                        index += 1;
+                    }
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        } else {
+            match rx.recv() {
+                Ok(command) => command,
+                Err(_) => return,
+            }
+        };
+
+        match command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""))
+        {
+            ("" | "n", _) => index = (index + 1).min(last),
+            ("b", _) => index = index.saturating_sub(1),
+            ("p", _) => playing = true,
+            ("r", _) => index = 0,
+            ("g", n) => {
+                if let Ok(n) = n.trim().parse::<usize>() {
+                    index = n.min(last);
+                }
+            }
+            ("q", _) => return,
+            _ => {}
+        }
+    }
+}
+
+fn load_log(crane: Crane) -> MoveLog {
+    let (cargo, moves) = include_str!("day5.txt").split_once("\n\n").expect("failed");
+
+    let cargo = cargo.lines().collect::<Vec<_>>();
+    let cargo = parse_cargo(&cargo[0..(cargo.len() - 1)]);
+
+    let moves = moves.lines().collect::<Vec<_>>();
+    let moves = parse_moves(&moves);
+
+    MoveLog::new(crane, cargo, moves)
+}
+
+fn query(options: &Options) {
+    let mut log = load_log(options.crane);
+
+    for at in &options.at {
+        log.seek(*at);
+        println!("after move {} : {}", log.position, log.tops());
+        println!("{}", render_cargo(&log.cargos));
+        println!();
+    }
+}
+
+fn animate(options: &Options) {
+    let mut log = load_log(options.crane);
+    let frames = build_frames(&mut log, options.every);
+
+    if let Some(path) = &options.export {
+        export_frames(&frames, path).expect("Failed to export frames");
+        println!("exported {} frames to {path}", frames.len());
+    }
+
+    if options.interactive {
+        run_controller(&frames);
+    }
+}
+
+/// Crate by crate implementations the bulk moves are benchmarked against
+fn naive_apply_single_move(cargos: &mut [Vec<char>], m: &Move) {
+    for _ in 0..m.length {
+        let el = cargos[m.source - 1].pop();
+        if let Some(e) = el {
+            cargos[m.target - 1].push(e);
+        }
+    }
+}
+
+fn naive_apply_single_move_2(cargos: &mut [Vec<char>], m: &Move) {
+    let source = &mut cargos[m.source - 1];
+    let mut package: Vec<char> = vec![];
+
+    for _ in 0..m.length {
+        if let Some(el) = source.pop() {
+            package.insert(0, el);
+        }
+    }
+
+    for e in package {
+        cargos[m.target - 1].push(e);
+    }
+}
+
+const BENCH_STACKS: usize = 9;
+const BENCH_HEIGHT: usize = 20_000;
+const BENCH_MOVES: usize = 2_000;
+const BENCH_MAX_LENGTH: usize = 5_000;
+
+/// `moves` random moves of up to `max_length` crates over stacks of `height`, generated with a
+/// fixed seed so every run benchmarks the same procedure
+fn stress_procedure(height: usize, moves: usize, max_length: usize) -> (Vec<Vec<char>>, Vec<Move>) {
+    let mut seed = 0x2022_u64;
+    let mut next = |bound: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % bound
+    };
+
+    let cargos = (0..BENCH_STACKS)
+        .map(|_| {
+            (0..height)
+                .map(|_| (b'A' + next(26) as u8) as char)
+                .collect()
+        })
+        .collect();
+    let moves = (0..moves)
+        .map(|_| Move {
+            length: 1 + next(max_length),
+            source: 1 + next(BENCH_STACKS),
+            target: 1 + next(BENCH_STACKS),
+        })
+        .collect();
+
+    (cargos, moves)
+}
+
+fn bench_crane(
+    name: &str,
+    cargos: &[Vec<char>],
+    moves: &[Move],
+    bulk: fn(&mut [Vec<char>], &Move) -> Vec<char>,
+    naive: fn(&mut [Vec<char>], &Move),
+) {
+    let mut bulk_cargos = cargos.to_vec();
+    let start = Instant::now();
+    for m in moves {
+        bulk(&mut bulk_cargos, m);
+    }
+    let bulk_time = start.elapsed();
+
+    let mut naive_cargos = cargos.to_vec();
+    let start = Instant::now();
+    for m in moves {
+        naive(&mut naive_cargos, m);
+    }
+    let naive_time = start.elapsed();
+
+    assert_eq!(bulk_cargos, naive_cargos, "{name} implementations disagree");
+    println!("{name} : bulk {bulk_time:?}, crate by crate {naive_time:?}");
+}
+
+fn bench() {
+    let (cargos, moves) = stress_procedure(BENCH_HEIGHT, BENCH_MOVES, BENCH_MAX_LENGTH);
+    println!(
+        "{BENCH_MOVES} moves of up to {BENCH_MAX_LENGTH} crates over {BENCH_STACKS} stacks of {BENCH_HEIGHT}"
+    );
+    bench_crane(
+        "CrateMover 9000",
+        &cargos,
+        &moves,
+        apply_single_move,
+        naive_apply_single_move,
+    );
+    bench_crane(
+        "CrateMover 9001",
+        &cargos,
+        &moves,
+        apply_single_move_2,
+        naive_apply_single_move_2,
+    );
+}
+
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let options = Options::from_args(&args);
+    if options.bench {
+        bench();
+        return;
+    }
+    if !options.at.is_empty() {
+        query(&options);
+        return;
+    }
+    if options.animate {
+        animate(&options);
+        return;
+    }
+
     part1();
     part2();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a drawing, footer included, the way the puzzle input is laid out
+    fn parse(drawing: &str) -> Vec<Vec<char>> {
+        let lines = drawing.lines().collect::<Vec<_>>();
+        parse_cargo(&lines[..lines.len() - 1])
+    }
+
+    fn assert_round_trips(drawing: &str) {
+        let cargos = parse(drawing);
+        assert_eq!(render_cargo(&cargos), drawing);
+        assert_eq!(parse(&render_cargo(&cargos)), cargos);
+    }
+
+    fn assert_round_trips_from_stacks(cargos: &[Vec<char>]) {
+        let drawing = render_cargo(cargos);
+        assert_eq!(parse(&drawing), cargos);
+        assert_eq!(render_cargo(&parse(&drawing)), drawing);
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let drawing = ["    [D]    ", "[N] [C]    ", "[Z] [M] [P]", " 1   2   3 "].join("\n");
+        assert_eq!(
+            parse(&drawing),
+            vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]
+        );
+        assert_round_trips(&drawing);
+    }
+
+    #[test]
+    fn uneven_heights() {
+        let drawing = [
+            "            [E]",
+            "    [B]     [D]",
+            "    [A]     [C]",
+            "[X] [Y]     [B]",
+            "[W] [Z] [Q] [A]",
+            " 1   2   3   4 ",
+        ]
+        .join("\n");
+        assert_round_trips(&drawing);
+        assert_round_trips_from_stacks(&[vec!['A'], vec!['B', 'C', 'D', 'E', 'F'], vec!['G', 'H']]);
+    }
+
+    #[test]
+    fn empty_stack() {
+        let drawing = ["[A]     [C]", "[B]     [D]", " 1   2   3 "].join("\n");
+        assert_eq!(
+            parse(&drawing),
+            vec![vec!['B', 'A'], vec![], vec!['D', 'C']]
+        );
+        assert_round_trips(&drawing);
+        assert_round_trips_from_stacks(&[vec!['A'], vec![], vec!['B', 'C']]);
+    }
+
+    #[test]
+    fn more_than_nine_stacks() {
+        let cargos = (0..12)
+            .map(|i| (0..i % 4 + 1).map(|h| (b'A' + i + h) as char).collect())
+            .collect::<Vec<Vec<char>>>();
+        assert_round_trips_from_stacks(&cargos);
+
+        let drawing = render_cargo(&cargos);
+        assert!(drawing.ends_with(" 9  10  11  12 "));
+        assert_round_trips(&drawing);
+    }
+
+    fn example() -> (Vec<Vec<char>>, Vec<Move>) {
+        let drawing = ["    [D]    ", "[N] [C]    ", "[Z] [M] [P]", " 1   2   3 "].join("\n");
+        let moves = parse_moves(&[
+            "move 1 from 2 to 1",
+            "move 3 from 1 to 3",
+            "move 2 from 2 to 1",
+            "move 1 from 1 to 2",
+        ]);
+        (parse(&drawing), moves)
+    }
+
+    #[test]
+    fn frames_start_with_the_initial_state_and_end_with_the_last_move() {
+        let (cargos, moves) = example();
+        for every in 1..=5 {
+            let mut log = MoveLog::new(Crane::CrateMover9000, cargos.clone(), moves.clone());
+            let frames = build_frames(&mut log, every);
+
+            assert_eq!(frames[0].title, "step 0/4 : initial state");
+            assert_eq!(frames[0].drawing, render_cargo(&cargos));
+
+            let last = frames.last().unwrap();
+            assert_eq!(last.title, "step 4/4 : move 1 from 1 to 2");
+            assert_eq!(
+                last.drawing,
+                render_cargo(&apply_move(cargos.clone(), moves.clone()))
+            );
+
+            let steps = (1..=4).filter(|s| s % every == 0 || *s == 4).count();
+            assert_eq!(frames.len(), 1 + steps, "every {every}");
+        }
+    }
+
+    /// Procedure longer than a few snapshot intervals, with moves onto their own source stack
+    /// and moves longer than their source stack
+    fn long_procedure() -> (Vec<Vec<char>>, Vec<Move>) {
+        let cargos = vec![
+            "ABCDEF".chars().collect(),
+            "GH".chars().collect(),
+            vec![],
+            "IJKL".chars().collect(),
+        ];
+        let moves = (0..SNAPSHOT_INTERVAL * 3 + 17)
+            .map(|i| Move {
+                length: 1 + i % 5,
+                source: 1 + i % 4,
+                target: 1 + (i * 7 / 3) % 4,
+            })
+            .collect::<Vec<_>>();
+        assert!(moves.iter().any(|m| m.source == m.target));
+        (cargos, moves)
+    }
+
+    fn replay(crane: Crane, cargos: &[Vec<char>], moves: &[Move]) -> Vec<Vec<char>> {
+        let mut cargos = cargos.to_vec();
+        for m in moves {
+            crane.apply(&mut cargos, m);
+        }
+        cargos
+    }
+
+    #[test]
+    fn seek_matches_a_full_replay() {
+        let (cargos, moves) = long_procedure();
+        for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+            let mut log = MoveLog::new(crane, cargos.clone(), moves.clone());
+            let positions = [
+                moves.len(),
+                0,
+                SNAPSHOT_INTERVAL + 1,
+                SNAPSHOT_INTERVAL - 1,
+                SNAPSHOT_INTERVAL * 3 + 5,
+                SNAPSHOT_INTERVAL * 2,
+                7,
+                SNAPSHOT_INTERVAL * 2 + 50,
+                moves.len() + 10,
+            ];
+            for position in positions {
+                log.seek(position);
+                let position = position.min(moves.len());
+                assert_eq!(log.position, position);
+                assert_eq!(
+                    log.cargos,
+                    replay(crane, &cargos, &moves[..position]),
+                    "{crane:?} at {position}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let (cargos, moves) = long_procedure();
+        for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+            let mut log = MoveLog::new(crane, cargos.clone(), moves.clone());
+            log.seek(SNAPSHOT_INTERVAL + 20);
+
+            while log.undo() {}
+            assert_eq!(log.position, 0);
+            assert_eq!(log.cargos, cargos);
+            assert!(!log.undo());
+
+            for position in 1..=30 {
+                assert!(log.redo());
+                assert_eq!(log.cargos, replay(crane, &cargos, &moves[..position]));
+            }
+
+            log.seek(moves.len());
+            assert!(!log.redo());
+            assert_eq!(log.cargos, replay(crane, &cargos, &moves));
+        }
+    }
+
+    #[test]
+    fn move_onto_its_own_stack() {
+        let (cargos, _) = example();
+        let moves = parse_moves(&["move 2 from 2 to 2", "move 5 from 1 to 1"]);
+        for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+            let mut log = MoveLog::new(crane, cargos.clone(), moves.clone());
+            log.seek(2);
+            assert_eq!(log.cargos, cargos, "{crane:?}");
+            log.seek(0);
+            assert_eq!(log.cargos, cargos, "{crane:?}");
+        }
+    }
+
+    fn assert_bulk_matches_naive(
+        bulk: fn(&mut [Vec<char>], &Move) -> Vec<char>,
+        naive: fn(&mut [Vec<char>], &Move),
+    ) {
+        let (cargos, mut moves) = stress_procedure(10, 500, 30);
+        moves.push(Move {
+            length: 10 * BENCH_STACKS + 1,
+            source: 1,
+            target: 2,
+        });
+
+        let mut bulk_cargos = cargos.clone();
+        let mut naive_cargos = cargos;
+        for m in &moves {
+            let source = naive_cargos[m.source - 1].clone();
+            let taken = bulk(&mut bulk_cargos, m);
+            naive(&mut naive_cargos, m);
+
+            assert_eq!(bulk_cargos, naive_cargos, "{m}");
+            assert_eq!(
+                taken,
+                source[source.len().saturating_sub(m.length)..],
+                "{m}"
+            );
+        }
+    }
+
+    #[test]
+    fn bulk_moves_match_crate_by_crate_moves() {
+        assert_bulk_matches_naive(apply_single_move, naive_apply_single_move);
+        assert_bulk_matches_naive(apply_single_move_2, naive_apply_single_move_2);
+    }
+}
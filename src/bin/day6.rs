@@ -1,27 +1,467 @@
-use std::collections::HashSet;
+use std::io::{ErrorKind, Read};
 
-fn first_marker(input: &str, len: usize) -> Option<usize> {
-    let chars = input.chars();
-    for (i, _) in chars.enumerate() {
-        input.chars().nth(i + len - 1)?;
-        let slice = input[i..=i + len - 1].chars().collect::<HashSet<_>>();
-        if slice.len() == len {
-            return Some(i + len);
+fn check_marker_len(len: usize) -> std::io::Result<()> {
+    if len == 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "Marker length must be positive",
+        ));
+    }
+    Ok(())
+}
+
+/// Finds the end of the first window of `len` all-distinct bytes, reading `source` in chunks so
+/// the whole stream never has to be held in memory
+fn first_marker_in(mut source: impl Read, len: usize) -> std::io::Result<Option<usize>> {
+    check_marker_len(len)?;
+
+    let mut window = vec![0_u8; len];
+    let mut counts = [0_usize; 256];
+    let mut distinct = 0_usize;
+    let mut position = 0_usize;
+    let mut buffer = [0_u8; 64 * 1024];
+
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => return Ok(None),
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for &byte in &buffer[..read] {
+            let slot = position % len;
+            if position >= len {
+                let old = window[slot] as usize;
+                counts[old] -= 1;
+                if counts[old] == 0 {
+                    distinct -= 1;
+                }
+            }
+
+            if counts[byte as usize] == 0 {
+                distinct += 1;
+            }
+            counts[byte as usize] += 1;
+            window[slot] = byte;
+            position += 1;
+
+            if distinct == len {
+                return Ok(Some(position));
+            }
+        }
+    }
+}
+
+/// Same as `first_marker_in` but starts looking at `offset`, the result is still an absolute
+/// position in `input`
+fn first_marker_from(input: &[u8], len: usize, offset: usize) -> Option<usize> {
+    let rest = input.get(offset..)?;
+    first_marker_in(rest, len)
+        .expect("Reading from a slice can't fail")
+        .map(|p| p + offset)
+}
+
+const PACKET_MARKER_LEN: usize = 4;
+const MESSAGE_MARKER_LEN: usize = 14;
+
+#[derive(Debug)]
+struct Packet<'a> {
+    /// Start of the start-of-packet marker
+    offset: usize,
+    /// End of the start-of-message marker, when the packet has one
+    message: Option<usize>,
+    /// Bytes between the start-of-message marker and the next packet
+    payload: &'a [u8],
+}
+
+/// Position of the first byte from `from` on that repeats one of the `len - 1` bytes before it,
+/// where any all-distinct run of `len` bytes going through `from` is broken
+fn run_break(input: &[u8], len: usize, from: usize) -> usize {
+    (from..input.len())
+        .find(|&p| input[p.saturating_sub(len - 1)..p].contains(&input[p]))
+        .unwrap_or(input.len())
+}
+
+/// Splits a device stream into packets : each one starts with a start-of-packet marker and holds
+/// a start-of-message marker. Its payload starts right after the message marker and ends where
+/// the next start-of-packet marker begins, that marker being looked for only once the
+/// all-distinct run of the message marker is broken, otherwise the payload would always be
+/// empty. A packet without a message marker carries the rest of the stream after its
+/// start-of-packet marker.
+fn split_packets(input: &[u8]) -> Vec<Packet<'_>> {
+    let mut packets = vec![];
+    let mut position = 0;
+
+    while let Some(packet_end) = first_marker_from(input, PACKET_MARKER_LEN, position) {
+        let offset = packet_end - PACKET_MARKER_LEN;
+        let Some(message) = first_marker_from(input, MESSAGE_MARKER_LEN, offset) else {
+            packets.push(Packet {
+                offset,
+                message: None,
+                payload: &input[packet_end..],
+            });
+            break;
+        };
+
+        let payload_search = run_break(input, PACKET_MARKER_LEN, message);
+        let end = first_marker_from(input, PACKET_MARKER_LEN, payload_search)
+            .map(|p| p - PACKET_MARKER_LEN)
+            .unwrap_or(input.len());
+        packets.push(Packet {
+            offset,
+            message: Some(message),
+            payload: &input[message..end],
+        });
+        position = end;
+    }
+
+    packets
+}
+
+fn packets(args: &[String]) {
+    if let Some(arg) = args.iter().find(|a| a.starts_with("--")) {
+        panic!("Unexpected argument '{arg}'");
+    }
+    let input = match args.first() {
+        Some(path) => std::fs::read(path).expect("Failed to read signal capture"),
+        None => include_str!("day6.txt").trim().as_bytes().to_vec(),
+    };
+
+    println!(
+        "{:>10} {:>10} {:>10}  payload",
+        "offset", "message", "length"
+    );
+    for packet in split_packets(&input) {
+        let message = packet.message.map(|m| m.to_string());
+        println!(
+            "{:>10} {:>10} {:>10}  {}",
+            packet.offset,
+            message.as_deref().unwrap_or("-"),
+            packet.payload.len(),
+            String::from_utf8_lossy(packet.payload)
+        );
+    }
+}
+
+#[derive(Debug)]
+struct MarkerReport {
+    /// Window lengths with the end position of every all-distinct window of that length
+    markers: Vec<(usize, Vec<usize>)>,
+    /// Start and length of the longest all-distinct run
+    longest_run: (usize, usize),
+}
+
+impl MarkerReport {
+    fn first(&self, len: usize) -> Option<usize> {
+        self.markers
+            .iter()
+            .find(|(l, _)| *l == len)
+            .and_then(|(_, positions)| positions.first().copied())
+    }
+
+    fn to_table(&self) -> String {
+        let mut table = format!(
+            "{:>8} {:>10} {:>10} {:>10}\n",
+            "length", "count", "first", "last"
+        );
+        for (len, positions) in &self.markers {
+            let first = positions.first().map(|p| p.to_string());
+            let last = positions.last().map(|p| p.to_string());
+            table.push_str(&format!(
+                "{len:>8} {:>10} {:>10} {:>10}\n",
+                positions.len(),
+                first.as_deref().unwrap_or("-"),
+                last.as_deref().unwrap_or("-"),
+            ));
         }
+
+        let (start, len) = self.longest_run;
+        table.push_str(&format!(
+            "longest all-distinct run : {len} bytes from {start} to {}",
+            start + len
+        ));
+        table
+    }
+
+    fn to_json(&self) -> String {
+        let markers = self
+            .markers
+            .iter()
+            .map(|(len, positions)| {
+                let positions = positions
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{{\"length\":{len},\"positions\":[{positions}]}}")
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let (start, len) = self.longest_run;
+        format!(
+            "{{\"markers\":[{markers}],\"longest_run\":{{\"start\":{start},\"end\":{},\"length\":{len}}}}}",
+            start + len
+        )
     }
-    unreachable!();
 }
 
-fn part1(input: &str) -> Option<usize> {
-    first_marker(input, 4)
+/// Reports, in a single pass over `source`, every position where an all-distinct window of each
+/// of the given lengths ends, along with the longest all-distinct run of the stream
+fn marker_report_in(mut source: impl Read, lengths: &[usize]) -> std::io::Result<MarkerReport> {
+    for len in lengths {
+        check_marker_len(*len)?;
+    }
+    let mut markers = lengths.iter().map(|len| (*len, vec![])).collect::<Vec<_>>();
+    let mut longest_run = (0, 0);
+
+    // Position right after the last occurrence of each byte, 0 when not seen yet
+    let mut last_seen = [0_usize; 256];
+    let mut run = 0_usize;
+    let mut position = 0_usize;
+    let mut buffer = [0_u8; 64 * 1024];
+
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for &byte in &buffer[..read] {
+            position += 1;
+            run = (run + 1).min(position - last_seen[byte as usize]);
+            last_seen[byte as usize] = position;
+
+            for (len, positions) in markers.iter_mut() {
+                if run >= *len {
+                    positions.push(position);
+                }
+            }
+            if run > longest_run.1 {
+                longest_run = (position - run, run);
+            }
+        }
+    }
+
+    Ok(MarkerReport {
+        markers,
+        longest_run,
+    })
 }
 
-fn part2(input: &str) -> Option<usize> {
-    first_marker(input, 14)
+fn marker_report(input: &str, lengths: &[usize]) -> MarkerReport {
+    marker_report_in(input.as_bytes(), lengths).expect("Reading from a str can't fail")
+}
+
+fn part1(report: &MarkerReport) -> Option<usize> {
+    report.first(4)
+}
+
+fn part2(report: &MarkerReport) -> Option<usize> {
+    report.first(14)
+}
+
+fn report(args: &[String]) {
+    let mut json = false;
+    let mut lengths = vec![4, 14];
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--lengths" => {
+                lengths = args
+                    .next()
+                    .expect("--lengths expects a comma separated list")
+                    .split(',')
+                    .map(|l| {
+                        l.trim()
+                            .parse()
+                            .ok()
+                            .filter(|l| *l > 0)
+                            .expect("Window length must be a positive number")
+                    })
+                    .collect();
+            }
+            _ if arg.starts_with("--") => panic!("Unexpected argument '{arg}'"),
+            _ => path = Some(arg),
+        }
+    }
+
+    let report = match path {
+        Some(path) => {
+            let file = std::fs::File::open(path).expect("Failed to open signal capture");
+            marker_report_in(file, &lengths).expect("Failed to read")
+        }
+        None => marker_report(include_str!("day6.txt").trim(), &lengths),
+    };
+
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report.to_table());
+    }
 }
 
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(|a| a.as_str()) == Some("--report") {
+        report(&args[1..]);
+        return;
+    }
+    if args.first().map(|a| a.as_str()) == Some("--packets") {
+        packets(&args[1..]);
+        return;
+    }
+
+    if let Some(path) = args.first() {
+        if path.starts_with("--") {
+            panic!("Unexpected argument '{path}'");
+        }
+        let len = args
+            .get(1)
+            .map(|l| {
+                l.parse()
+                    .ok()
+                    .filter(|l| *l > 0)
+                    .expect("Window length must be a positive number")
+            })
+            .unwrap_or(4);
+        let file = std::fs::File::open(path).expect("Failed to open signal capture");
+        println!("{:?}", first_marker_in(file, len).expect("Failed to read"));
+        return;
+    }
+
     let input = include_str!("day6.txt").trim();
-    println!("{:?}", part1(input));
-    println!("{:?}", part2(input));
+    let report = marker_report(input, &[4, 14]);
+    println!("{:?}", part1(&report));
+    println!("{:?}", part2(&report));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLES: [(&str, usize, usize); 5] = [
+        ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+        ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+        ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+        ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+        ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+    ];
+
+    fn first_marker(input: &str, len: usize) -> Option<usize> {
+        first_marker_in(input.as_bytes(), len).unwrap()
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        for (input, packet, message) in EXAMPLES {
+            assert_eq!(first_marker(input, 4), Some(packet), "{input}");
+            assert_eq!(first_marker(input, 14), Some(message), "{input}");
+        }
+    }
+
+    #[test]
+    fn stream_without_marker() {
+        assert_eq!(first_marker("", 4), None);
+        assert_eq!(first_marker("abcabcabcabc", 4), None);
+        assert_eq!(first_marker("abc", 4), None);
+    }
+
+    #[test]
+    fn marker_across_reads() {
+        // The reading buffer holds 64 KiB, the marker spans its end
+        let mut input = vec![b'a'; 64 * 1024 - 2];
+        input.extend_from_slice(b"bcd");
+        assert_eq!(first_marker_in(&input[..], 4).unwrap(), Some(64 * 1024 + 1));
+    }
+
+    #[test]
+    fn zero_length_is_rejected() {
+        assert!(first_marker_in(&b"abcd"[..], 0).is_err());
+        assert!(marker_report_in(&b"abcd"[..], &[4, 0]).is_err());
+    }
+
+    #[test]
+    fn packets_carry_their_payload() {
+        let packets = split_packets(b"aaabcdefghijklmnxxwvutsrqponmlkjihgyy");
+        let summary = packets
+            .iter()
+            .map(|p| (p.offset, p.message, p.payload))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(2, Some(16), &b"x"[..]), (17, Some(31), &b"jihgyy"[..]),]
+        );
+    }
+
+    #[test]
+    fn packet_without_message_keeps_the_rest_of_the_stream() {
+        let packets = split_packets(b"zzabcdzz");
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].offset, 1);
+        assert_eq!(packets[0].message, None);
+        assert_eq!(packets[0].payload, b"dzz");
+    }
+
+    /// Longest all-distinct run as `(start, length)`, the first one on ties
+    fn brute_force_longest_run(input: &[u8]) -> (usize, usize) {
+        let mut longest = (0, 0);
+        for start in 0..input.len() {
+            let mut seen = [false; 256];
+            let len = input[start..]
+                .iter()
+                .take_while(|b| !std::mem::replace(&mut seen[**b as usize], true))
+                .count();
+            if len > longest.1 {
+                longest = (start, len);
+            }
+        }
+        longest
+    }
+
+    #[test]
+    fn report_matches_first_marker() {
+        for (input, _, _) in EXAMPLES {
+            let report = marker_report(input, &[4, 14, 3]);
+            for len in [4, 14, 3] {
+                assert_eq!(report.first(len), first_marker(input, len), "{input} {len}");
+            }
+            assert_eq!(
+                report.longest_run,
+                brute_force_longest_run(input.as_bytes()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn report_output() {
+        let report = marker_report("abcabcd", &[3, 4, 5]);
+        assert_eq!(report.longest_run, (3, 4));
+        assert_eq!(
+            report.to_table(),
+            [
+                "  length      count      first       last",
+                "       3          5          3          7",
+                "       4          1          7          7",
+                "       5          0          -          -",
+                "longest all-distinct run : 4 bytes from 3 to 7",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            report.to_json(),
+            concat!(
+                r#"{"markers":[{"length":3,"positions":[3,4,5,6,7]},"#,
+                r#"{"length":4,"positions":[7]},{"length":5,"positions":[]}],"#,
+                r#""longest_run":{"start":3,"end":7,"length":4}}"#
+            )
+        );
+    }
 }
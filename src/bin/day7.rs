@@ -1,195 +1,1834 @@
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    io::{BufRead, Write},
+    str::FromStr,
+};
 
-#[derive(Clone, Eq, PartialEq, Default)]
-struct FileTree {
-    files: Vec<(String, u32)>,
-    dirs: Vec<(String, Rc<RefCell<FileTree>>)>,
-    parent: Option<Rc<RefCell<FileTree>>>,
+use anyhow::anyhow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NodeId(usize);
+
+#[derive(Debug, Default)]
+struct Dir {
+    name: String,
+    parent: Option<NodeId>,
+    files: Vec<(String, u64)>,
+    dirs: Vec<NodeId>,
+    /// Index of every file in `files` by name
+    file_index: HashMap<String, usize>,
+    /// Every subdirectory by name
+    dir_index: HashMap<String, NodeId>,
 }
 
-impl Debug for FileTree {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("FileTree")
-            .field("files", &self.files)
-            .field("dirs", &self.dirs)
-            .field("parent_linked", &self.parent.is_some())
-            .finish()
+/// Directory tree stored in an arena : nodes refer to each other through their `NodeId`, the
+/// root being always the first one
+#[derive(Debug)]
+struct Filesystem {
+    dirs: Vec<Dir>,
+    sizes: OnceCell<Vec<u64>>,
+}
+
+impl Default for Filesystem {
+    fn default() -> Self {
+        Self {
+            dirs: vec![Dir::default()],
+            sizes: OnceCell::new(),
+        }
     }
 }
 
-fn parse_input(
-    input: &[&str],
-    current_dir: Option<Rc<RefCell<FileTree>>>,
-) -> Option<Rc<RefCell<FileTree>>> {
-    if input.is_empty() {
-        return current_dir;
+impl Filesystem {
+    fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn dir(&self, id: NodeId) -> &Dir {
+        &self.dirs[id.0]
     }
-    let current = input[0];
 
-    let mut lines = current.lines();
-    let command = lines.next().expect("No command");
-    let command_output = lines.collect::<Vec<_>>();
+    fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.dir(id).parent
+    }
 
-    let (command, payload) = if command.find(' ').is_some() {
-        command.split_once(' ').unwrap()
-    } else {
-        (command, "")
-    };
+    fn child(&self, id: NodeId, name: &str) -> Option<NodeId> {
+        self.dir(id).dir_index.get(name).copied()
+    }
 
-    match command {
-        "ls" => {
-            let mut files = vec![];
-            let mut dirs = vec![];
-
-            for o in command_output {
-                let (size_or_dir, name) =
-                    o.split_once(' ').expect("No separator in command output");
-
-                if size_or_dir == "dir" {
-                    let file_tree = FileTree {
-                        parent: current_dir.clone(),
-                        ..Default::default()
-                    };
-                    dirs.push((name.to_string(), Rc::new(RefCell::new(file_tree))));
-                } else {
-                    let size: u32 = size_or_dir.parse().expect("Could not parse file size");
-                    files.push((name.to_string(), size));
-                }
+    /// Subdirectory or file called `name` in `id`
+    fn entry(&self, id: NodeId, name: &str) -> Option<Node> {
+        let dir = self.dir(id);
+        dir.dir_index
+            .get(name)
+            .map(|child| Node::Dir(*child))
+            .or_else(|| dir.file_index.get(name).map(|i| Node::File(id, *i)))
+    }
+
+    fn get_or_create_child(&mut self, id: NodeId, name: &str) -> NodeId {
+        if let Some(child) = self.child(id, name) {
+            return child;
+        }
+
+        let child = NodeId(self.dirs.len());
+        self.dirs.push(Dir {
+            name: name.to_string(),
+            parent: Some(id),
+            ..Default::default()
+        });
+        let parent = &mut self.dirs[id.0];
+        parent.dirs.push(child);
+        parent.dir_index.insert(name.to_string(), child);
+        self.sizes.take();
+        child
+    }
+
+    /// Listing the same directory twice must not count its files twice, a file seen again
+    /// only has its size updated
+    fn add_file(&mut self, id: NodeId, name: &str, size: u64) {
+        let dir = &mut self.dirs[id.0];
+        match dir.file_index.get(name) {
+            Some(i) => dir.files[*i].1 = size,
+            None => {
+                dir.file_index.insert(name.to_string(), dir.files.len());
+                dir.files.push((name.to_string(), size));
             }
+        }
+        self.sizes.take();
+    }
 
-            let cur_ls = if let Some(dir) = current_dir {
-                let mut tree = dir.as_ref().borrow_mut();
-                tree.files.append(&mut files);
-                tree.files.dedup();
-                tree.dirs.append(&mut dirs);
-                tree.dirs.dedup();
-                dir.clone()
-            } else {
-                Rc::new(RefCell::new(FileTree {
-                    files,
-                    dirs,
-                    parent: None,
-                }))
+    /// Follows an absolute or relative `path` from `from`, creating the directories that were
+    /// not listed yet. Going up from the root stays at the root
+    fn cd(&mut self, from: NodeId, path: &str) -> NodeId {
+        let mut current = if path.starts_with('/') {
+            self.root()
+        } else {
+            from
+        };
+
+        for part in path.split('/') {
+            current = match part {
+                "" | "." => current,
+                ".." => self.parent(current).unwrap_or(current),
+                name => self.get_or_create_child(current, name),
             };
-            parse_input(&input[1..input.len()], Some(cur_ls))
-        }
-        "cd" => {
-            if payload != ".." {
-                if let Some(dirw) = current_dir {
-                    let cur_cd = {
-                        let mut dir = dirw.as_ref().borrow_mut();
-                        let cur_cd = if let Some((_, tree)) =
-                            dir.dirs.iter().cloned().find(|(name, _)| name == payload)
-                        {
-                            tree
-                        } else {
-                            let new_dir = Rc::new(RefCell::new(FileTree {
-                                files: vec![],
-                                dirs: vec![],
-                                parent: Some(dirw.clone()),
-                            }));
-                            dir.dirs.push((payload.to_string(), new_dir.clone()));
-                            new_dir
-                        };
-
-                        cur_cd
-                    };
-
-                    parse_input(&input[1..input.len()], Some(cur_cd))
-                } else {
-                    let cur_cd_2 = Rc::new(RefCell::new(FileTree {
-                        parent: None,
-                        ..Default::default()
-                    }));
-                    let tree = Rc::new(RefCell::new(FileTree {
-                        files: vec![],
-                        dirs: vec![(payload.to_string(), cur_cd_2.clone())],
-                        parent: None,
-                    }));
-                    let mut t = cur_cd_2.as_ref().borrow_mut();
-                    t.parent = Some(tree);
-                    parse_input(&input[1..input.len()], Some(cur_cd_2.clone()))
+        }
+        current
+    }
+
+    /// Cumulative size of every directory, computed once for the whole tree. Children are always
+    /// created after their parent, so a single reverse sweep over the arena rolls sizes up
+    fn sizes(&self) -> &[u64] {
+        self.sizes.get_or_init(|| {
+            let mut sizes = self
+                .dirs
+                .iter()
+                .map(|d| d.files.iter().map(|(_, size)| size).sum())
+                .collect::<Vec<u64>>();
+
+            for (i, dir) in self.dirs.iter().enumerate().rev() {
+                if let Some(parent) = dir.parent {
+                    sizes[parent.0] += sizes[i];
                 }
-            } else {
-                let cur_cd_3 = if let Some(dir) = current_dir {
-                    let dir = dir.as_ref().borrow();
-                    dir.parent.clone()
-                } else {
-                    current_dir
-                };
-                parse_input(&input[1..input.len()], cur_cd_3)
             }
+            sizes
+        })
+    }
+
+    fn dir_ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.dirs.len()).map(NodeId)
+    }
+
+    fn children(&self, id: NodeId) -> Vec<(&str, Node)> {
+        let dir = self.dir(id);
+        dir.dirs
+            .iter()
+            .map(|d| (self.dir(*d).name.as_str(), Node::Dir(*d)))
+            .chain(
+                dir.files
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, _))| (name.as_str(), Node::File(id, i))),
+            )
+            .collect()
+    }
+
+    fn size(&self, node: Node) -> u64 {
+        match node {
+            Node::Dir(id) => self.sizes()[id.0],
+            Node::File(id, i) => self.dir(id).files[i].1,
+        }
+    }
+
+    /// Looks up an absolute path without creating anything, `..` going up as in `cd`
+    fn get(&self, path: &str) -> Option<Node> {
+        self.resolve(self.root(), path)
+    }
+
+    /// Same as `get` but relative paths start from `from`
+    fn resolve(&self, from: NodeId, path: &str) -> Option<Node> {
+        let mut current = Node::Dir(if path.starts_with('/') {
+            self.root()
+        } else {
+            from
+        });
+
+        for part in path.split('/') {
+            let Node::Dir(id) = current else {
+                return None;
+            };
+            current = match part {
+                "" | "." => current,
+                ".." => Node::Dir(self.parent(id).unwrap_or(id)),
+                name => self.entry(id, name)?,
+            };
+        }
+        Some(current)
+    }
+
+    fn walk(&self) -> Walk<'_> {
+        let root = self.root();
+        Walk {
+            fs: self,
+            stack: vec![Entry {
+                path: "/".to_string(),
+                node: Node::Dir(root),
+                size: self.size(Node::Dir(root)),
+                depth: 0,
+            }],
+        }
+    }
+
+    fn find<'a>(&'a self, query: &'a Find) -> impl Iterator<Item = Entry> + 'a {
+        self.walk().filter(|entry| query.matches(entry))
+    }
+
+    fn path(&self, id: NodeId) -> String {
+        let mut parts = vec![];
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            parts.push(self.dir(current).name.as_str());
+            current = parent;
+        }
+        parts.reverse();
+        format!("/{}", parts.join("/"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Dir(NodeId),
+    /// Directory holding the file and index of the file in it
+    File(NodeId, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    File,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    path: String,
+    node: Node,
+    size: u64,
+    depth: usize,
+}
+
+impl Entry {
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or("")
+    }
+
+    fn kind(&self) -> Kind {
+        match self.node {
+            Node::Dir(_) => Kind::Dir,
+            Node::File(..) => Kind::File,
+        }
+    }
+}
+
+/// Depth-first walk over every directory and file, children in name order
+struct Walk<'a> {
+    fs: &'a Filesystem,
+    stack: Vec<Entry>,
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.stack.pop()?;
+
+        if let Node::Dir(id) = entry.node {
+            let base = entry.path.trim_end_matches('/');
+            let mut children = self.fs.children(id);
+            children.sort_by(|(a, _), (b, _)| b.cmp(a));
+            self.stack
+                .extend(children.into_iter().map(|(name, node)| Entry {
+                    path: format!("{base}/{name}"),
+                    size: self.fs.size(node),
+                    node,
+                    depth: entry.depth + 1,
+                }));
+        }
+
+        Some(entry)
+    }
+}
+
+/// `find`-style filters, every criteria left to `None` matches anything
+#[derive(Debug, Default)]
+struct Find {
+    name: Option<String>,
+    kind: Option<Kind>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
+}
+
+impl Find {
+    fn matches(&self, entry: &Entry) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|glob| glob_match(glob, entry.name()))
+            && self.kind.is_none_or(|k| k == entry.kind())
+            && self.min_size.is_none_or(|s| entry.size >= s)
+            && self.max_size.is_none_or(|s| entry.size <= s)
+            && self.min_depth.is_none_or(|d| entry.depth >= d)
+            && self.max_depth.is_none_or(|d| entry.depth <= d)
+    }
+}
+
+/// Shell-style glob supporting `*` and `?`
+fn glob_match(glob: &str, name: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut g, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, n));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    g = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
         }
-        _ => panic!("Unexpected command '{command}'"),
     }
+
+    glob[g..].iter().all(|c| *c == '*')
 }
 
-fn get_root(mut file_tree: FileTree) -> FileTree {
-    while let Some(parent) = file_tree.parent {
-        file_tree = parent.as_ref().borrow().clone();
+/// Commands of a transcript, plus the ones only the interactive shell understands
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Cd(String),
+    Ls(Option<String>),
+    Pwd,
+    Du(Option<String>),
+    Find(String),
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, payload) = s.split_once(' ').unwrap_or((s, ""));
+        let payload = payload.trim();
+        let argument = (!payload.is_empty()).then(|| payload.to_string());
+
+        match command {
+            "ls" => Ok(Self::Ls(argument)),
+            "cd" if argument.is_some() => Ok(Self::Cd(payload.to_string())),
+            "pwd" if argument.is_none() => Ok(Self::Pwd),
+            "du" => Ok(Self::Du(argument)),
+            "find" if argument.is_some() => Ok(Self::Find(payload.to_string())),
+            "cd" | "pwd" | "find" => Err(anyhow!("Invalid arguments for {command} : '{payload}'")),
+            _ => Err(anyhow!("Unknown command '{command}'")),
+        }
     }
-    file_tree
 }
 
-fn get_dir_size(root_fs: FileTree) -> u64 {
-    let file_sizes = root_fs
-        .files
+/// Rebuilds the filesystem from a terminal transcript, one line at a time. Lines starting with
+/// `$ ` are commands, anything else is the output of the last `ls`
+fn parse_input(input: &str) -> anyhow::Result<Filesystem> {
+    let mut fs = Filesystem::default();
+    let mut current_dir = fs.root();
+    let mut listing = false;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(command) = line.strip_prefix("$ ") {
+            let command = command
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("line {line_number} : {e}"))?;
+
+            listing = match command {
+                Command::Ls(None) => true,
+                Command::Cd(path) => {
+                    current_dir = fs.cd(current_dir, &path);
+                    false
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "line {line_number} : only cd and ls can appear in a transcript, found '{line}'"
+                    ))
+                }
+            };
+            continue;
+        }
+
+        if !listing {
+            return Err(anyhow!(
+                "line {line_number} : output '{line}' does not follow an ls command"
+            ));
+        }
+
+        add_listing_entry(&mut fs, current_dir, line)
+            .map_err(|e| anyhow!("line {line_number} : {e}"))?;
+    }
+
+    Ok(fs)
+}
+
+/// Adds a `dir <name>` or `<size> <name>` line of an `ls` output to `dir`
+fn add_listing_entry(fs: &mut Filesystem, dir: NodeId, line: &str) -> anyhow::Result<()> {
+    let (size_or_dir, name) = line
+        .split_once(' ')
+        .ok_or(anyhow!("no separator in ls output '{line}'"))?;
+
+    if size_or_dir == "dir" {
+        fs.get_or_create_child(dir, name);
+    } else {
+        let size: u64 = size_or_dir
+            .parse()
+            .map_err(|e| anyhow!("invalid file size '{size_or_dir}' : {e}"))?;
+        fs.add_file(dir, name, size);
+    }
+    Ok(())
+}
+
+fn sorted_children(fs: &Filesystem, id: NodeId) -> Vec<(&str, Node)> {
+    let mut children = fs.children(id);
+    children.sort_by_key(|(name, _)| *name);
+    children
+}
+
+fn listing_line(fs: &Filesystem, name: &str, node: Node) -> String {
+    match node {
+        Node::Dir(_) => format!("dir {name}"),
+        file => format!("{} {name}", fs.size(file)),
+    }
+}
+
+/// Canonical `ls -R`-style dump : one `<path>:` section per directory, depth first, holding
+/// the same lines `ls` prints in a transcript
+fn to_ls_r(fs: &Filesystem) -> String {
+    let mut sections = vec![];
+    for entry in fs.walk() {
+        let Node::Dir(id) = entry.node else {
+            continue;
+        };
+        let mut section = format!("{}:\n", entry.path);
+        for (name, node) in sorted_children(fs, id) {
+            section.push_str(&listing_line(fs, name, node));
+            section.push('\n');
+        }
+        sections.push(section);
+    }
+    sections.join("\n")
+}
+
+fn from_ls_r(input: &str) -> anyhow::Result<Filesystem> {
+    let mut fs = Filesystem::default();
+    let mut current_dir = None;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(path) = line.strip_suffix(':').filter(|p| p.starts_with('/')) {
+            current_dir = Some(fs.cd(fs.root(), path));
+            continue;
+        }
+
+        let dir = current_dir.ok_or(anyhow!(
+            "line {line_number} : entry '{line}' outside of a directory section"
+        ))?;
+        add_listing_entry(&mut fs, dir, line).map_err(|e| anyhow!("line {line_number} : {e}"))?;
+    }
+
+    Ok(fs)
+}
+
+/// Shortest `$ cd`/`$ ls` session listing every directory once, which `parse_input` turns back
+/// into the same filesystem
+fn to_transcript(fs: &Filesystem) -> String {
+    let mut lines = vec!["$ cd /".to_string()];
+    write_transcript(fs, fs.root(), &mut lines);
+
+    while lines.last().is_some_and(|l| l.starts_with("$ cd ..")) {
+        lines.pop();
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Pushes a `cd`, merged with the previous one when nothing happened in between
+fn push_cd(lines: &mut Vec<String>, path: &str) {
+    match lines.last_mut() {
+        Some(last) if last.starts_with("$ cd ") && last != "$ cd /" => {
+            last.push('/');
+            last.push_str(path);
+        }
+        _ => lines.push(format!("$ cd {path}")),
+    }
+}
+
+fn write_transcript(fs: &Filesystem, id: NodeId, lines: &mut Vec<String>) {
+    let children = sorted_children(fs, id);
+    lines.push("$ ls".to_string());
+    for (name, node) in &children {
+        lines.push(listing_line(fs, name, *node));
+    }
+
+    for (name, node) in children {
+        if let Node::Dir(child) = node {
+            push_cd(lines, name);
+            write_transcript(fs, child, lines);
+            push_cd(lines, "..");
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Nested `{"name", "files", "dirs"}` objects, starting from the root named `/`
+fn to_json(fs: &Filesystem) -> String {
+    dir_to_json(fs, fs.root())
+}
+
+fn dir_to_json(fs: &Filesystem, id: NodeId) -> String {
+    let dir = fs.dir(id);
+    let name = if id == fs.root() { "/" } else { &dir.name };
+
+    let mut files = dir.files.iter().collect::<Vec<_>>();
+    files.sort();
+    let files = files
         .iter()
-        .map(|(_name, size)| *size as u64)
-        .sum::<u64>();
-    let dirs_sizes = root_fs
+        .map(|(name, size)| format!("{{\"name\":{},\"size\":{size}}}", json_string(name)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut dirs = dir.dirs.clone();
+    dirs.sort_by_key(|d| &fs.dir(*d).name);
+    let dirs = dirs
+        .into_iter()
+        .map(|d| dir_to_json(fs, d))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(
+        "{{\"name\":{},\"files\":[{files}],\"dirs\":[{dirs}]}}",
+        json_string(name)
+    )
+}
+
+#[derive(Debug)]
+enum Json {
+    /// `null`, `true` or `false`, none of which the filesystem format uses
+    Literal,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> anyhow::Result<&Json> {
+        let Json::Object(fields) = self else {
+            return Err(anyhow!("Expected an object holding '{key}'"));
+        };
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .ok_or(anyhow!("Missing field '{key}'"))
+    }
+
+    fn as_str(&self) -> anyhow::Result<&str> {
+        match self {
+            Json::String(s) => Ok(s),
+            v => Err(anyhow!("Expected a string but found {v:?}")),
+        }
+    }
+
+    fn as_array(&self) -> anyhow::Result<&[Json]> {
+        match self {
+            Json::Array(a) => Ok(a),
+            v => Err(anyhow!("Expected an array but found {v:?}")),
+        }
+    }
+
+    fn as_u64(&self) -> anyhow::Result<u64> {
+        match self {
+            Json::Number(n) => n
+                .parse()
+                .map_err(|e| anyhow!("Expected a size but found {n} : {e}")),
+            v => Err(anyhow!("Expected a number but found {v:?}")),
+        }
+    }
+}
+
+/// Minimal JSON reader, just enough to read back what `to_json` writes or a hand-written tree
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn parse(input: &str) -> anyhow::Result<Json> {
+        let mut parser = JsonParser {
+            chars: input.chars().peekable(),
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(anyhow!("Unexpected '{c}' after the JSON value")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            c => Err(anyhow!("Expected '{expected}' but found {c:?}")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> anyhow::Result<Json> {
+        for expected in keyword.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(anyhow!("Invalid literal, expected {keyword}"));
+            }
+        }
+        Ok(Json::Literal)
+    }
+
+    fn value(&mut self) -> anyhow::Result<Json> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('n') => self.keyword("null"),
+            Some('t') => self.keyword("true"),
+            Some('f') => self.keyword("false"),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    number.push(c);
+                }
+                Ok(Json::Number(number))
+            }
+            c => Err(anyhow!("Unexpected {c:?} at the start of a JSON value")),
+        }
+    }
+
+    fn object(&mut self) -> anyhow::Result<Json> {
+        self.expect('{')?;
+        let mut fields = vec![];
+
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                c => return Err(anyhow!("Expected ',' or '}}' but found {c:?}")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> anyhow::Result<Json> {
+        self.expect('[')?;
+        let mut values = vec![];
+
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                c => return Err(anyhow!("Expected ',' or ']' but found {c:?}")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let code = (0..4)
+                            .map(|_| self.chars.next())
+                            .collect::<Option<String>>()
+                            .ok_or(anyhow!("Unterminated unicode escape"))?;
+                        let code = u32::from_str_radix(&code, 16)
+                            .map_err(|e| anyhow!("Invalid unicode escape {code} : {e}"))?;
+                        s.push(char::from_u32(code).ok_or(anyhow!("Invalid character {code}"))?);
+                    }
+                    Some(c) => s.push(c),
+                    None => return Err(anyhow!("Unterminated string")),
+                },
+                Some(c) => s.push(c),
+                None => return Err(anyhow!("Unterminated string")),
+            }
+        }
+    }
+}
+
+fn from_json(input: &str) -> anyhow::Result<Filesystem> {
+    let json = JsonParser::parse(input)?;
+    let mut fs = Filesystem::default();
+    let root = fs.root();
+    dir_from_json(&mut fs, root, &json)?;
+    Ok(fs)
+}
+
+fn dir_from_json(fs: &mut Filesystem, id: NodeId, json: &Json) -> anyhow::Result<()> {
+    for file in json.get("files")?.as_array()? {
+        fs.add_file(id, file.get("name")?.as_str()?, file.get("size")?.as_u64()?);
+    }
+    for dir in json.get("dirs")?.as_array()? {
+        let child = fs.get_or_create_child(id, dir.get("name")?.as_str()?);
+        dir_from_json(fs, child, dir)?;
+    }
+    Ok(())
+}
+
+fn get_dir_size(fs: &Filesystem, id: NodeId) -> u64 {
+    fs.sizes()[id.0]
+}
+
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1}{}", UNITS[unit])
+}
+
+enum TreeEntry<'a> {
+    Dir(NodeId),
+    File(&'a str, u64),
+}
+
+/// `tree`-like listing where every directory shows its cumulative size
+fn render_tree(fs: &Filesystem) -> String {
+    let root = fs.root();
+    let mut output = format!("/ ({})\n", get_dir_size(fs, root));
+    render_tree_children(fs, root, "", &mut output);
+    output
+}
+
+fn render_tree_children(fs: &Filesystem, id: NodeId, prefix: &str, output: &mut String) {
+    let dir = fs.dir(id);
+    let mut entries = dir
         .dirs
         .iter()
-        .map(|(_name, tree)| get_dir_size(tree.as_ref().borrow().clone()))
-        .sum::<u64>();
+        .map(|d| (fs.dir(*d).name.as_str(), TreeEntry::Dir(*d)))
+        .chain(
+            dir.files
+                .iter()
+                .map(|(name, size)| (name.as_str(), TreeEntry::File(name, *size))),
+        )
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(name, _)| *name);
+
+    let count = entries.len();
+    for (i, (_, entry)) in entries.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let branch = if last { "└── " } else { "├── " };
+        match entry {
+            TreeEntry::Dir(child) => {
+                output.push_str(&format!(
+                    "{prefix}{branch}{}/ ({})\n",
+                    fs.dir(child).name,
+                    get_dir_size(fs, child)
+                ));
+                let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+                render_tree_children(fs, child, &prefix, output);
+            }
+            TreeEntry::File(name, size) => {
+                output.push_str(&format!("{prefix}{branch}{name} ({size})\n"));
+            }
+        }
+    }
+}
+
+/// Every directory with its cumulative size, largest first
+fn dirs_by_size(fs: &Filesystem) -> Vec<(NodeId, u64)> {
+    let mut dirs = fs
+        .dir_ids()
+        .map(|id| (id, get_dir_size(fs, id)))
+        .collect::<Vec<_>>();
+    dirs.sort_by_cached_key(|(id, size)| (std::cmp::Reverse(*size), fs.path(*id)));
+    dirs
+}
+
+/// `du -h`-style table, limited to the `top` largest directories when given
+fn render_du(fs: &Filesystem, top: Option<usize>) -> String {
+    dirs_by_size(fs)
+        .into_iter()
+        .take(top.unwrap_or(usize::MAX))
+        .map(|(id, size)| format!("{:>8}\t{}\n", human_size(size), fs.path(id)))
+        .collect()
+}
+
+fn part1(fs: &Filesystem, max_dir_size: u64) -> u64 {
+    let query = Find {
+        kind: Some(Kind::Dir),
+        max_size: Some(max_dir_size),
+        min_depth: Some(1),
+        ..Default::default()
+    };
+    fs.find(&query).map(|entry| entry.size).sum()
+}
+
+#[derive(Debug)]
+struct CleanupPlan {
+    disk_size: u64,
+    required_free_space: u64,
+    used: u64,
+    /// Space that still has to be freed, 0 when there already is enough
+    needed: u64,
+    /// Smallest single directory freeing enough space on its own
+    smallest: Option<Entry>,
+    /// Greedy pick of the fewest non-nested directories freeing enough space together : the
+    /// largest top level directories, the last one swapped for the smallest directory outside
+    /// of the others that covers what they leave to free. The number of directories is always
+    /// the minimum, but another set of that many directories may free less space
+    greedy_set: Option<Vec<Entry>>,
+}
+
+/// Plans which directories to delete so that `required_free_space` is available on a disk of
+/// `disk_size`. The root itself is never a candidate
+fn plan_cleanup(
+    fs: &Filesystem,
+    disk_size: u64,
+    required_free_space: u64,
+) -> anyhow::Result<CleanupPlan> {
+    let used = get_dir_size(fs, fs.root());
+    if used > disk_size {
+        return Err(anyhow!(
+            "Filesystem uses {used} which does not fit on a disk of {disk_size}"
+        ));
+    }
+    let needed = required_free_space.saturating_sub(disk_size - used);
+
+    let mut plan = CleanupPlan {
+        disk_size,
+        required_free_space,
+        used,
+        needed,
+        smallest: None,
+        greedy_set: None,
+    };
+    if needed == 0 {
+        plan.greedy_set = Some(vec![]);
+        return Ok(plan);
+    }
+
+    let query = Find {
+        kind: Some(Kind::Dir),
+        min_size: Some(needed),
+        min_depth: Some(1),
+        ..Default::default()
+    };
+    plan.smallest = fs.find(&query).min_by_key(|entry| entry.size);
+
+    // A directory always frees at least as much as any set of its descendants, so the fewest
+    // non-nested directories are the largest top level ones
+    let top_level = Find {
+        kind: Some(Kind::Dir),
+        min_depth: Some(1),
+        max_depth: Some(1),
+        ..Default::default()
+    };
+    let mut candidates = fs.find(&top_level).collect::<Vec<_>>();
+    candidates.sort_by_key(|entry| std::cmp::Reverse(entry.size));
+
+    let mut freed = 0;
+    let mut set = vec![];
+    for candidate in candidates {
+        if freed >= needed {
+            break;
+        }
+        freed += candidate.size;
+        set.push(candidate);
+    }
+    if freed >= needed {
+        let last = set.pop().expect("At least one directory is needed");
+        let remaining = needed - (freed - last.size);
+        let query = Find {
+            kind: Some(Kind::Dir),
+            min_size: Some(remaining),
+            min_depth: Some(1),
+            ..Default::default()
+        };
+        let last = fs
+            .find(&query)
+            .filter(|entry| {
+                !set.iter().any(|picked| {
+                    entry.path == picked.path
+                        || entry.path.starts_with(&format!("{}/", picked.path))
+                })
+            })
+            .min_by_key(|entry| entry.size)
+            .unwrap_or(last);
+        set.push(last);
+        plan.greedy_set = Some(set);
+    }
+
+    Ok(plan)
+}
+
+impl CleanupPlan {
+    fn explain(&self) -> String {
+        let free = self.disk_size - self.used;
+        let mut lines = vec![format!(
+            "disk size {}, used {}, free {free}, required free space {}",
+            self.disk_size, self.used, self.required_free_space
+        )];
+
+        if self.needed == 0 {
+            lines.push("there already is enough free space, nothing to delete".to_string());
+            return lines.join("\n");
+        }
+        lines.push(format!("{} more bytes have to be freed", self.needed));
+
+        match &self.smallest {
+            Some(entry) => lines.push(format!(
+                "smallest single directory : {} ({}), leaving {} free",
+                entry.path,
+                entry.size,
+                free + entry.size
+            )),
+            None => lines.push("no single directory frees enough space".to_string()),
+        }
+
+        match &self.greedy_set {
+            Some(set) => {
+                lines.push(format!(
+                    "fewest non-nested directories to delete, picked greedily : {}",
+                    set.len()
+                ));
+                let mut freed = 0;
+                for entry in set {
+                    freed += entry.size;
+                    lines.push(format!(
+                        "  {} ({}), {freed} freed so far",
+                        entry.path, entry.size
+                    ));
+                }
+                lines.push(format!("leaving {} free", free + freed));
+            }
+            None => lines.push("deleting every directory does not free enough space".to_string()),
+        }
+
+        lines.join("\n")
+    }
+}
 
-    file_sizes + dirs_sizes
+fn part2(
+    fs: &Filesystem,
+    disk_size: u64,
+    required_free_space: u64,
+) -> anyhow::Result<(Option<(String, u64)>, u64)> {
+    let plan = plan_cleanup(fs, disk_size, required_free_space)?;
+    let smallest = plan
+        .smallest
+        .map(|entry| (entry.name().to_string(), entry.size));
+    Ok((smallest, plan.needed))
 }
 
-fn part1(root_fs: FileTree, cur: &mut u64) -> &mut u64 {
-    for (_dirname, tree) in root_fs.dirs {
-        let dir_tree = tree.as_ref().borrow().clone();
-        let dir_size = get_dir_size(dir_tree.clone());
-        if dir_size <= 100000 {
-            *cur += dir_size;
+const PART1_MAX_DIR_SIZE: u64 = 100000;
+const DISK_SIZE: u64 = 70000000;
+const REQUIRED_FREE_SPACE: u64 = 30000000;
+
+#[derive(Debug, Default)]
+struct FsDiff {
+    added: Vec<(String, u64)>,
+    removed: Vec<(String, u64)>,
+    /// Path, old size and new size
+    changed: Vec<(String, u64, u64)>,
+    added_dirs: Vec<String>,
+    removed_dirs: Vec<String>,
+    /// Change of the cumulative size of every directory that grew or shrank
+    dir_deltas: Vec<(String, i64)>,
+}
+
+fn sizes_by_path(fs: &Filesystem, kind: Kind) -> BTreeMap<String, u64> {
+    fs.walk()
+        .filter(|entry| entry.kind() == kind)
+        .map(|entry| (entry.path, entry.size))
+        .collect()
+}
+
+/// Compares two reconstructed filesystems by path. Directory deltas come from the cumulative
+/// sizes, so a change deep in the tree shows on every one of its ancestors
+fn diff(old: &Filesystem, new: &Filesystem) -> FsDiff {
+    let mut diff = FsDiff::default();
+
+    let old_files = sizes_by_path(old, Kind::File);
+    let new_files = sizes_by_path(new, Kind::File);
+    for (path, size) in &old_files {
+        match new_files.get(path) {
+            None => diff.removed.push((path.clone(), *size)),
+            Some(new_size) if new_size != size => {
+                diff.changed.push((path.clone(), *size, *new_size))
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, size) in &new_files {
+        if !old_files.contains_key(path) {
+            diff.added.push((path.clone(), *size));
+        }
+    }
+
+    let old_dirs = sizes_by_path(old, Kind::Dir);
+    let new_dirs = sizes_by_path(new, Kind::Dir);
+    let all_dirs = old_dirs
+        .keys()
+        .chain(new_dirs.keys())
+        .collect::<BTreeSet<_>>();
+    for path in all_dirs {
+        let old_size = old_dirs.get(path);
+        let new_size = new_dirs.get(path);
+        match (old_size, new_size) {
+            (Some(_), None) => diff.removed_dirs.push(path.clone()),
+            (None, Some(_)) => diff.added_dirs.push(path.clone()),
+            _ => {}
+        }
+
+        let delta = *new_size.unwrap_or(&0) as i64 - *old_size.unwrap_or(&0) as i64;
+        if delta != 0 {
+            diff.dir_deltas.push((path.clone(), delta));
+        }
+    }
+
+    diff
+}
+
+impl Display for FsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for path in &self.added_dirs {
+            writeln!(f, "+ {path}/")?;
+        }
+        for path in &self.removed_dirs {
+            writeln!(f, "- {path}/")?;
+        }
+        for (path, size) in &self.added {
+            writeln!(f, "+ {path} ({size})")?;
+        }
+        for (path, size) in &self.removed {
+            writeln!(f, "- {path} ({size})")?;
+        }
+        for (path, old, new) in &self.changed {
+            writeln!(
+                f,
+                "~ {path} {old} -> {new} ({:+})",
+                *new as i64 - *old as i64
+            )?;
+        }
+
+        if self.dir_deltas.is_empty() {
+            return writeln!(f, "no size change");
+        }
+        writeln!(f, "directory size deltas :")?;
+        for (path, delta) in &self.dir_deltas {
+            writeln!(f, "{delta:>+12}  {path}")?;
         }
-        part1(dir_tree, cur);
+        Ok(())
     }
-    cur
 }
 
-fn part2(root_fs: FileTree, min_dir_size: u64, cur: &mut (String, u64)) -> &mut (String, u64) {
-    for (dirname, tree) in root_fs.dirs {
-        let dir_tree = tree.as_ref().borrow().clone();
-        let dir_size = get_dir_size(dir_tree.clone());
-        if dir_size >= min_dir_size && dir_size < cur.1 {
-            *cur = (dirname, dir_size);
+#[derive(Clone, Copy)]
+enum Format {
+    Transcript,
+    Json,
+    LsR,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "transcript" => Self::Transcript,
+            "json" => Self::Json,
+            "ls-r" => Self::LsR,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid format, expected one of transcript, json, ls-r but found {s}"
+                ))
+            }
+        })
+    }
+}
+
+enum Report {
+    Tree,
+    Du,
+    Top(usize),
+    Get(String),
+    Find(Find),
+    Plan,
+    Export(Format),
+    Diff(String),
+    Shell,
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: Option<String>) -> T {
+    value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| panic!("{arg} expects a number"))
+}
+
+struct Shell<'a> {
+    fs: &'a Filesystem,
+    current_dir: NodeId,
+}
+
+impl Shell<'_> {
+    fn dir_at(&self, path: Option<&str>) -> anyhow::Result<NodeId> {
+        let Some(path) = path else {
+            return Ok(self.current_dir);
+        };
+        match self.fs.resolve(self.current_dir, path) {
+            Some(Node::Dir(id)) => Ok(id),
+            Some(Node::File(..)) => Err(anyhow!("{path} : not a directory")),
+            None => Err(anyhow!("{path} : no such file or directory")),
         }
-        part2(dir_tree, min_dir_size, cur);
     }
-    cur
+
+    fn execute(&mut self, command: Command) -> anyhow::Result<String> {
+        let fs = self.fs;
+        Ok(match command {
+            Command::Cd(path) => {
+                self.current_dir = self.dir_at(Some(&path))?;
+                String::new()
+            }
+            Command::Pwd => format!("{}\n", fs.path(self.current_dir)),
+            Command::Ls(path) => {
+                let dir = self.dir_at(path.as_deref())?;
+                sorted_children(fs, dir)
+                    .into_iter()
+                    .map(|(name, node)| listing_line(fs, name, node) + "\n")
+                    .collect()
+            }
+            Command::Du(path) => {
+                let dir = self.dir_at(path.as_deref())?;
+                let mut output = String::new();
+                for (name, node) in sorted_children(fs, dir) {
+                    if let Node::Dir(_) = node {
+                        output.push_str(&format!("{:>8}\t{name}\n", human_size(fs.size(node))));
+                    }
+                }
+                output.push_str(&format!("{:>8}\t.\n", human_size(get_dir_size(fs, dir))));
+                output
+            }
+            Command::Find(glob) => {
+                let base = fs.path(self.current_dir);
+                let prefix = base.trim_end_matches('/').to_string() + "/";
+                let query = Find {
+                    name: Some(glob),
+                    ..Default::default()
+                };
+                fs.find(&query)
+                    .filter(|entry| entry.path == base || entry.path.starts_with(&prefix))
+                    .map(|entry| format!("{:>10}  {}\n", entry.size, entry.path))
+                    .collect()
+            }
+        })
+    }
+}
+
+const SHELL_HELP: &str = "commands : cd <path>, ls [path], du [path], find <glob>, pwd, help, exit";
+
+/// Small interactive shell to look around a reconstructed filesystem
+fn run_shell(fs: &Filesystem) {
+    let mut shell = Shell {
+        fs,
+        current_dir: fs.root(),
+    };
+    let stdin = std::io::stdin();
+
+    println!("{SHELL_HELP}");
+    loop {
+        print!("{} $ ", fs.path(shell.current_dir));
+        std::io::stdout().flush().ok();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "exit" | "quit" => break,
+            "help" => {
+                println!("{SHELL_HELP}");
+                continue;
+            }
+            _ => {}
+        }
+
+        match line.parse().and_then(|command| shell.execute(command)) {
+            Ok(output) => print!("{output}"),
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+fn load(path: Option<&str>, format: Format) -> Filesystem {
+    let input = match path {
+        Some(path) => std::fs::read_to_string(path).expect("Failed to read transcript"),
+        None => include_str!("day7.txt").to_string(),
+    };
+    match format {
+        Format::Transcript => parse_input(&input),
+        Format::Json => from_json(&input),
+        Format::LsR => from_ls_r(&input),
+    }
+    .expect("Failed to parse input")
 }
 
 fn main() {
-    let input = include_str!("day7.txt")
-        .split('$')
-        .map(|c| c.trim())
-        .filter(|c| !c.is_empty())
-        .collect::<Vec<_>>();
+    let mut report = None;
+    let mut path = None;
+    let mut disk_size = DISK_SIZE;
+    let mut required_free_space = REQUIRED_FREE_SPACE;
+    let mut format = Format::Transcript;
 
-    let first_3 = &input[1..input.len()];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tree" => report = Some(Report::Tree),
+            "--du" => report = Some(Report::Du),
+            "--top" => {
+                let n = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--top expects a number of directories");
+                report = Some(Report::Top(n));
+            }
+            "--get" => report = Some(Report::Get(args.next().expect("--get expects a path"))),
+            "--find" => report = Some(Report::Find(Find::default())),
+            "--plan" => report = Some(Report::Plan),
+            "--shell" => report = Some(Report::Shell),
+            "--diff" => {
+                let old = args.next().expect("--diff expects the old transcript");
+                report = Some(Report::Diff(old));
+            }
+            "--from" => {
+                format = args
+                    .next()
+                    .expect("--from expects a format")
+                    .parse()
+                    .expect("Invalid --from format")
+            }
+            "--export" => {
+                let format = args
+                    .next()
+                    .expect("--export expects a format")
+                    .parse()
+                    .expect("Invalid --export format");
+                report = Some(Report::Export(format));
+            }
+            "--disk-size" => disk_size = parse_number(&arg, args.next()),
+            "--required" => required_free_space = parse_number(&arg, args.next()),
+            "--name" | "--type" | "--min-size" | "--max-size" | "--min-depth" | "--max-depth" => {
+                let Some(Report::Find(query)) = &mut report else {
+                    panic!("{arg} is only valid after --find");
+                };
+                let value = args.next();
+                match arg.as_str() {
+                    "--name" => query.name = Some(value.expect("--name expects a glob")),
+                    "--type" => {
+                        query.kind = match value.as_deref() {
+                            Some("d") => Some(Kind::Dir),
+                            Some("f") => Some(Kind::File),
+                            v => panic!("--type expects d or f but found {v:?}"),
+                        }
+                    }
+                    "--min-size" => query.min_size = Some(parse_number(&arg, value)),
+                    "--max-size" => query.max_size = Some(parse_number(&arg, value)),
+                    "--min-depth" => query.min_depth = Some(parse_number(&arg, value)),
+                    _ => query.max_depth = Some(parse_number(&arg, value)),
+                }
+            }
+            _ if arg.starts_with("--") => panic!("Unexpected argument '{arg}'"),
+            _ => path = Some(arg),
+        }
+    }
 
-    let fs = parse_input(first_3, Some(Default::default())).expect("Expect FS");
-    let root_fs = get_root(fs.as_ref().borrow().clone());
+    let fs = load(path.as_deref(), format);
+
+    match report {
+        Some(Report::Tree) => return print!("{}", render_tree(&fs)),
+        Some(Report::Du) => return print!("{}", render_du(&fs, None)),
+        Some(Report::Top(n)) => return print!("{}", render_du(&fs, Some(n))),
+        Some(Report::Get(path)) => {
+            match fs.get(&path) {
+                Some(node @ Node::Dir(_)) => println!("{path} : dir, {}", fs.size(node)),
+                Some(node) => println!("{path} : file, {}", fs.size(node)),
+                None => println!("{path} : not found"),
+            }
+            return;
+        }
+        Some(Report::Find(query)) => {
+            for entry in fs.find(&query) {
+                println!("{:>10}  {}", entry.size, entry.path);
+            }
+            return;
+        }
+        Some(Report::Export(Format::Transcript)) => return print!("{}", to_transcript(&fs)),
+        Some(Report::Export(Format::Json)) => return println!("{}", to_json(&fs)),
+        Some(Report::Export(Format::LsR)) => return print!("{}", to_ls_r(&fs)),
+        Some(Report::Diff(old)) => {
+            let old = load(Some(&old), format);
+            return print!("{}", diff(&old, &fs));
+        }
+        Some(Report::Shell) => return run_shell(&fs),
+        Some(Report::Plan) => {
+            match plan_cleanup(&fs, disk_size, required_free_space) {
+                Ok(plan) => println!("{}", plan.explain()),
+                Err(e) => println!("{e}"),
+            }
+            return;
+        }
+        None => {}
+    }
 
-    let mut part1_size = 0_u64;
-    part1(root_fs.clone(), &mut part1_size);
-    println!("part 1 : {part1_size}");
+    println!("part 1 : {}", part1(&fs, PART1_MAX_DIR_SIZE));
 
-    let dirs_size = get_dir_size(root_fs.clone());
-    let min_dir_size = dirs_size - 40000000;
-    let mut part2_dir = (":(".to_string(), u64::MAX);
-    part2(root_fs, min_dir_size, &mut part2_dir);
+    let (part2_dir, min_dir_size) =
+        part2(&fs, disk_size, required_free_space).expect("Failed to plan the cleanup");
+    let part2_dir = part2_dir.unwrap_or((":(".to_string(), u64::MAX));
 
     println!("part 2 : {part2_dir:?} (min : {min_dir_size})");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k
+";
+
+    fn example() -> Filesystem {
+        parse_input(EXAMPLE).unwrap()
+    }
+
+    fn size_at(fs: &Filesystem, path: &str) -> Option<u64> {
+        fs.get(path).map(|node| fs.size(node))
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let fs = example();
+        assert_eq!(part1(&fs, PART1_MAX_DIR_SIZE), 95437);
+
+        let (smallest, _) = part2(&fs, DISK_SIZE, REQUIRED_FREE_SPACE).unwrap();
+        assert_eq!(smallest, Some(("d".to_string(), 24933642)));
+    }
+
+    #[test]
+    fn cd_to_root_in_the_middle_of_a_transcript() {
+        let fs = parse_input("$ cd /\n$ cd a\n$ ls\n1 x\n$ cd /\n$ ls\n2 y\n").unwrap();
+        assert_eq!(size_at(&fs, "/a/x"), Some(1));
+        assert_eq!(size_at(&fs, "/y"), Some(2));
+        assert_eq!(size_at(&fs, "/a/y"), None);
+        assert_eq!(size_at(&fs, "/"), Some(3));
+    }
+
+    #[test]
+    fn absolute_and_relative_cd() {
+        let fs =
+            parse_input("$ cd /a/b\n$ ls\n5 f\n$ cd ../c\n$ ls\n7 g\n$ cd ./d\n$ ls\n1 h").unwrap();
+        assert_eq!(size_at(&fs, "/a/b/f"), Some(5));
+        assert_eq!(size_at(&fs, "/a/c/g"), Some(7));
+        assert_eq!(size_at(&fs, "/a/c/d/h"), Some(1));
+        assert_eq!(size_at(&fs, "/a"), Some(13));
+    }
+
+    #[test]
+    fn cd_up_from_the_root() {
+        let fs = parse_input("$ cd /\n$ cd ..\n$ cd ../..\n$ ls\n3 f\n").unwrap();
+        assert_eq!(size_at(&fs, "/f"), Some(3));
+        assert_eq!(fs.children(fs.root()).len(), 1);
+    }
+
+    #[test]
+    fn repeated_ls_counts_once() {
+        let fs = parse_input("$ ls\n10 f\ndir d\n$ cd d\n$ ls\n4 g\n$ cd ..\n$ ls\n10 f\ndir d\n")
+            .unwrap();
+        assert_eq!(fs.children(fs.root()).len(), 2);
+        assert_eq!(size_at(&fs, "/"), Some(14));
+    }
+
+    #[test]
+    fn invalid_transcripts_are_errors() {
+        for (transcript, error) in [
+            ("$ cd /\n$ rm -rf /", "line 2 : Unknown command 'rm'"),
+            (
+                "$ pwd",
+                "line 1 : only cd and ls can appear in a transcript",
+            ),
+            (
+                "$ cd /\n14 f",
+                "line 2 : output '14 f' does not follow an ls command",
+            ),
+            ("$ ls\nabc f", "line 2 : invalid file size 'abc'"),
+            (
+                "$ ls\nlonely",
+                "line 2 : no separator in ls output 'lonely'",
+            ),
+        ] {
+            let message = parse_input(transcript)
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_default();
+            assert!(
+                message.starts_with(error),
+                "{transcript:?} gave {message:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tree_of_the_example() {
+        assert_eq!(
+            render_tree(&example()),
+            "\
+/ (48381165)
+├── a/ (94853)
+│   ├── e/ (584)
+│   │   └── i (584)
+│   ├── f (29116)
+│   ├── g (2557)
+│   └── h.lst (62596)
+├── b.txt (14848514)
+├── c.dat (8504156)
+└── d/ (24933642)
+    ├── d.ext (5626152)
+    ├── d.log (8033020)
+    ├── j (4060174)
+    └── k (7214296)
+"
+        );
+    }
+
+    #[test]
+    fn du_of_the_example() {
+        let fs = example();
+        assert_eq!(
+            render_du(&fs, None),
+            "   46.1M\t/\n   23.8M\t/d\n   92.6K\t/a\n     584\t/a/e\n"
+        );
+        assert_eq!(render_du(&fs, Some(2)), "   46.1M\t/\n   23.8M\t/d\n");
+    }
+
+    #[test]
+    fn glob_backtracking() {
+        for (glob, name) in [
+            ("*", ""),
+            ("*", "d.log"),
+            ("d.*", "d.log"),
+            ("*.l?t", "h.lst"),
+            ("*a*b", "aaab"),
+            ("a*b*c", "abcbc"),
+            ("*x*", "axbxc"),
+            ("?", "g"),
+            ("**.txt", "b.txt"),
+        ] {
+            assert!(glob_match(glob, name), "{glob} should match {name}");
+        }
+        for (glob, name) in [
+            ("?", ""),
+            ("??", "g"),
+            ("*.txt", "b.txt.bak"),
+            ("a*b", "aaba"),
+            ("*a*b", "bbba"),
+            ("d", "d.log"),
+        ] {
+            assert!(!glob_match(glob, name), "{glob} should not match {name}");
+        }
+    }
+
+    fn paths(fs: &Filesystem, query: &Find) -> Vec<String> {
+        fs.find(query).map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn find_filters() {
+        let fs = example();
+        let walked = fs.walk().map(|entry| entry.path).collect::<Vec<_>>();
+        assert_eq!(walked.len(), 14);
+        assert_eq!(walked[..4], ["/", "/a", "/a/e", "/a/e/i"]);
+
+        let by_name = Find {
+            name: Some("d*".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(paths(&fs, &by_name), ["/d", "/d/d.ext", "/d/d.log"]);
+
+        let small_dirs = Find {
+            kind: Some(Kind::Dir),
+            max_size: Some(100000),
+            ..Default::default()
+        };
+        assert_eq!(paths(&fs, &small_dirs), ["/a", "/a/e"]);
+
+        let large_files = Find {
+            kind: Some(Kind::File),
+            min_size: Some(8000000),
+            ..Default::default()
+        };
+        assert_eq!(paths(&fs, &large_files), ["/b.txt", "/c.dat", "/d/d.log"]);
+
+        let depth = Find {
+            min_depth: Some(2),
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(&fs, &depth),
+            ["/a/e", "/a/f", "/a/g", "/a/h.lst", "/d/d.ext", "/d/d.log", "/d/j", "/d/k"]
+        );
+        let root_only = Find {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(paths(&fs, &root_only), ["/"]);
+    }
+
+    #[test]
+    fn get_by_path() {
+        let fs = example();
+        assert_eq!(size_at(&fs, "/a/e/i"), Some(584));
+        assert_eq!(size_at(&fs, "/a/e"), Some(584));
+        assert_eq!(size_at(&fs, "/a/e/../../d/./j"), Some(4060174));
+        assert_eq!(size_at(&fs, "/../a"), Some(94853));
+        assert_eq!(size_at(&fs, "/a/f/x"), None);
+        assert_eq!(size_at(&fs, "/missing"), None);
+        assert!(matches!(fs.get("/d"), Some(Node::Dir(_))));
+        assert!(matches!(fs.get("/d/k"), Some(Node::File(..))));
+    }
+
+    fn entry_paths(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn cleanup_of_the_example() {
+        let plan = plan_cleanup(&example(), DISK_SIZE, REQUIRED_FREE_SPACE).unwrap();
+        assert_eq!(plan.used, 48381165);
+        assert_eq!(plan.needed, 8381165);
+        assert_eq!(plan.smallest.as_ref().map(|e| e.path.as_str()), Some("/d"));
+        assert_eq!(entry_paths(plan.greedy_set.as_deref().unwrap()), ["/d"]);
+    }
+
+    #[test]
+    fn cleanup_with_enough_free_space() {
+        let fs = example();
+        let plan = plan_cleanup(&fs, DISK_SIZE, 0).unwrap();
+        assert_eq!(plan.needed, 0);
+        assert_eq!(plan.greedy_set.map(|set| set.len()), Some(0));
+
+        // Less than 40M used leaves more than the required 30M free
+        let plan = plan_cleanup(&fs, 80000000, REQUIRED_FREE_SPACE).unwrap();
+        assert_eq!(plan.needed, 0);
+        assert!(plan.smallest.is_none());
+        assert!(plan.explain().contains("nothing to delete"));
+    }
+
+    #[test]
+    fn cleanup_of_an_overfull_disk() {
+        let error = plan_cleanup(&example(), 40000000, REQUIRED_FREE_SPACE).unwrap_err();
+        assert!(error.to_string().contains("does not fit"), "{error}");
+    }
+
+    #[test]
+    fn cleanup_needing_two_directories() {
+        let fs = parse_input(
+            "$ ls\ndir a\ndir b\ndir c\n$ cd a\n$ ls\n60 f\n$ cd ../b\n$ ls\n50 g\ndir x\n\
+             $ cd x\n$ ls\n30 h\n$ cd /c\n$ ls\n20 i\n",
+        )
+        .unwrap();
+        let plan = plan_cleanup(&fs, 200, 140).unwrap();
+        assert_eq!(plan.needed, 100);
+        assert!(plan.smallest.is_none());
+        // /b and /a would free 140, /c is enough next to /b
+        assert_eq!(
+            entry_paths(plan.greedy_set.as_deref().unwrap()),
+            ["/b", "/c"]
+        );
+
+        let plan = plan_cleanup(&fs, 200, 201).unwrap();
+        assert!(plan.greedy_set.is_none());
+    }
+
+    /// Every entry of the tree with its size, in walk order
+    fn snapshot(fs: &Filesystem) -> Vec<(String, u64, Kind)> {
+        fs.walk()
+            .map(|entry| (entry.path.clone(), entry.size, entry.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let with_empty_dir = "$ cd /\n$ ls\ndir empty\ndir full\n$ cd full\n$ ls\n3 f\n";
+        for transcript in [EXAMPLE, with_empty_dir] {
+            let fs = parse_input(transcript).unwrap();
+            let expected = snapshot(&fs);
+
+            assert_eq!(snapshot(&from_json(&to_json(&fs)).unwrap()), expected);
+            assert_eq!(snapshot(&from_ls_r(&to_ls_r(&fs)).unwrap()), expected);
+            assert_eq!(
+                snapshot(&parse_input(&to_transcript(&fs)).unwrap()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn transcript_merges_cd() {
+        let fs = parse_input("$ cd /a/c\n$ ls\n1 x\n$ cd /b\n$ ls\n2 y\n").unwrap();
+        assert_eq!(
+            to_transcript(&fs),
+            "\
+$ cd /
+$ ls
+dir a
+dir b
+$ cd a
+$ ls
+dir c
+$ cd c
+$ ls
+1 x
+$ cd ../../b
+$ ls
+2 y
+"
+        );
+    }
+
+    #[test]
+    fn json_escaping() {
+        let mut fs = Filesystem::default();
+        let dir = fs.cd(fs.root(), "we\\ird");
+        fs.add_file(dir, "q\"uote\\slash", 5);
+
+        let json = to_json(&fs);
+        assert!(json.contains(r#""name":"we\\ird""#), "{json}");
+        assert!(json.contains(r#""name":"q\"uote\\slash""#), "{json}");
+        assert_eq!(
+            snapshot(&from_json(&json).unwrap()),
+            [
+                ("/".to_string(), 5, Kind::Dir),
+                ("/we\\ird".to_string(), 5, Kind::Dir),
+                ("/we\\ird/q\"uote\\slash".to_string(), 5, Kind::File),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_exports_are_errors() {
+        for json in [
+            "",
+            r#"{"name":"/","files":[}"#,
+            r#"{"name":"/","files":[]}"#,
+            r#"{"name":"/","files":[{"name":"f","size":-1}],"dirs":[]}"#,
+            r#"{"name":"/","files":[],"dirs":[]} trailing"#,
+        ] {
+            assert!(from_json(json).is_err(), "{json}");
+        }
+        for ls_r in ["1 f", "/:\nabc f", "/:\nlonely"] {
+            assert!(from_ls_r(ls_r).is_err(), "{ls_r}");
+        }
+    }
+
+    #[test]
+    fn diff_shows_on_every_ancestor() {
+        let old = example();
+        let new = EXAMPLE
+            .replace("14848514 b.txt\n", "")
+            .replace("584 i", "600 i")
+            .replace("62596 h.lst\n", "62596 h.lst\n100 new\ndir z\n")
+            + "$ cd /a/z\n$ ls\n10 w\n";
+        let new = parse_input(&new).unwrap();
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.added,
+            [("/a/new".to_string(), 100), ("/a/z/w".to_string(), 10)]
+        );
+        assert_eq!(diff.removed, [("/b.txt".to_string(), 14848514)]);
+        assert_eq!(diff.changed, [("/a/e/i".to_string(), 584, 600)]);
+        assert_eq!(diff.added_dirs, ["/a/z"]);
+        assert!(diff.removed_dirs.is_empty());
+        assert_eq!(
+            diff.dir_deltas,
+            [
+                ("/".to_string(), 126 - 14848514),
+                ("/a".to_string(), 126),
+                ("/a/e".to_string(), 16),
+                ("/a/z".to_string(), 10),
+            ]
+        );
+        assert!(diff.to_string().contains("~ /a/e/i 584 -> 600 (+16)"));
+    }
+
+    fn run(shell: &mut Shell, command: &str) -> anyhow::Result<String> {
+        shell.execute(command.parse()?)
+    }
+
+    #[test]
+    fn shell_commands() {
+        let fs = example();
+        let mut shell = Shell {
+            fs: &fs,
+            current_dir: fs.root(),
+        };
+
+        let error = run(&mut shell, "cd b.txt").unwrap_err();
+        assert_eq!(error.to_string(), "b.txt : not a directory");
+        assert!(run(&mut shell, "cd nope").is_err());
+        assert_eq!(run(&mut shell, "pwd").unwrap(), "/\n");
+
+        run(&mut shell, "cd ..").unwrap();
+        assert_eq!(run(&mut shell, "pwd").unwrap(), "/\n");
+
+        run(&mut shell, "cd a").unwrap();
+        assert_eq!(run(&mut shell, "ls e").unwrap(), "584 i\n");
+        assert_eq!(
+            run(&mut shell, "ls ../d/..").unwrap(),
+            run(&mut shell, "ls /").unwrap()
+        );
+        assert_eq!(run(&mut shell, "du").unwrap(), "     584\te\n   92.6K\t.\n");
+        assert_eq!(run(&mut shell, "du ../d").unwrap(), "   23.8M\t.\n");
+
+        // find only looks below the current directory
+        assert_eq!(run(&mut shell, "find *").unwrap().lines().count(), 6);
+        assert_eq!(run(&mut shell, "find d*").unwrap(), "");
+        run(&mut shell, "cd /").unwrap();
+        assert_eq!(
+            run(&mut shell, "find d*").unwrap(),
+            "  24933642  /d\n   5626152  /d/d.ext\n   8033020  /d/d.log\n"
+        );
+    }
+}
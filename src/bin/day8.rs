@@ -1,175 +1,1137 @@
-fn parse_input(input: &str) -> Vec<Vec<u8>> {
-    input
+use anyhow::anyhow;
+
+type Height = i64;
+
+/// How trees are written in a height map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeightFormat {
+    /// One digit per tree, as in the puzzle input
+    Digits,
+    /// Whitespace separated integers
+    Integers,
+    /// `Integers` when any row holds whitespace between trees, `Digits` otherwise. A single
+    /// column of multi-digit heights has no such row and needs `Integers` explicitly
+    Detect,
+}
+
+/// Reads a height map written in `format`, the same for every row. Every row must hold the same
+/// number of trees
+fn parse_input(input: &str, format: HeightFormat) -> anyhow::Result<Vec<Vec<Height>>> {
+    let format = match format {
+        HeightFormat::Detect
+            if input
+                .lines()
+                .any(|l| l.trim().contains(char::is_whitespace)) =>
+        {
+            HeightFormat::Integers
+        }
+        HeightFormat::Detect => HeightFormat::Digits,
+        format => format,
+    };
+
+    let rows = input
         .lines()
-        .map(|l| {
-            l.chars()
-                .map(|c| c.to_string().parse().expect("Invalid char"))
-                .collect()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            let line_number = i + 1;
+            let cells: Vec<String> = if format == HeightFormat::Integers {
+                l.split_whitespace().map(|c| c.to_string()).collect()
+            } else {
+                l.trim().chars().map(|c| c.to_string()).collect()
+            };
+
+            let row = cells
+                .iter()
+                .enumerate()
+                .map(|(column, c)| {
+                    c.parse().map_err(|e| {
+                        anyhow!(
+                            "line {line_number}, column {} : invalid height '{c}' : {e}",
+                            column + 1
+                        )
+                    })
+                })
+                .collect::<anyhow::Result<Vec<Height>>>()?;
+            Ok((line_number, row))
         })
-        .collect()
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let Some((_, first)) = rows.first() else {
+        return Err(anyhow!("The height map holds no tree"));
+    };
+    let width = first.len();
+    if let Some((line_number, row)) = rows.iter().find(|(_, row)| row.len() != width) {
+        return Err(anyhow!(
+            "line {line_number} has {} trees but the first row has {width}, rows must all have the same length",
+            row.len()
+        ));
+    }
+
+    Ok(rows.into_iter().map(|(_, row)| row).collect())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    North,
+    South,
+    West,
+    East,
+    NorthWest,
+    NorthEast,
+    SouthWest,
+    SouthEast,
 }
 
-fn is_visible_up(input: &Vec<Vec<u8>>, height: Option<u8>, x: usize, y: usize) -> bool {
-    let current = input[y][x];
-    if let Some(h) = height {
-        if current >= h {
-            return false;
+impl Direction {
+    const CARDINALS: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+        Direction::NorthWest,
+        Direction::NorthEast,
+        Direction::SouthWest,
+        Direction::SouthEast,
+    ];
+
+    /// Step along the x and y axes, y going down
+    fn components(&self) -> (isize, isize) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+            Direction::East => (1, 0),
+            Direction::NorthWest => (-1, -1),
+            Direction::NorthEast => (1, -1),
+            Direction::SouthWest => (-1, 1),
+            Direction::SouthEast => (1, 1),
         }
     }
 
-    if y == 0 {
-        return true;
+    fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+            Direction::NorthWest => Direction::SouthEast,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::SouthWest => Direction::NorthEast,
+            Direction::SouthEast => Direction::NorthWest,
+        }
+    }
+
+    fn move_coordinates(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        let (dx, dy) = self.components();
+        (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy))
+    }
+
+    fn is_edge(&self, input: &[Vec<Height>], (x, y): (usize, usize)) -> bool {
+        let (dx, dy) = self.components();
+        (dx < 0 && x == 0)
+            || (dx > 0 && x == input[0].len() - 1)
+            || (dy < 0 && y == 0)
+            || (dy > 0 && y == input.len() - 1)
+    }
+
+    /// Every line of the grid going along `self`, each one listed from the edge `self` points
+    /// to, so a tree always comes after the trees it looks at
+    fn lines(&self, input: &[Vec<Height>]) -> Vec<Vec<(usize, usize)>> {
+        let (width, height) = (input[0].len(), input.len());
+        let (dx, dy) = self.components();
+
+        // The row and the column `self` points to, their shared corner listed once
+        let start_y = if dy < 0 { 0 } else { height - 1 };
+        let start_x = if dx < 0 { 0 } else { width - 1 };
+        let mut starts = vec![];
+        if dy != 0 {
+            starts.extend((0..width).map(|x| (x, start_y)));
+        }
+        if dx != 0 {
+            starts.extend(
+                (0..height)
+                    .filter(|y| dy == 0 || *y != start_y)
+                    .map(|y| (start_x, y)),
+            );
+        }
+
+        let step = self.opposite();
+        starts
+            .into_iter()
+            .map(|mut coordinates| {
+                let mut line = vec![coordinates];
+                while !step.is_edge(input, coordinates) {
+                    coordinates = step.move_coordinates(coordinates);
+                    line.push(coordinates);
+                }
+                line
+            })
+            .collect()
     }
+}
 
-    is_visible_up(input, Some(height.unwrap_or(current)), x, y - 1)
+/// What blocks the view of a tree
+#[derive(Debug, Clone)]
+struct SightRules {
+    directions: Vec<Direction>,
+    /// Farthest a tree can see, and be seen from
+    max_distance: Option<usize>,
+    /// How far above the top of its tree the observer stands
+    observer_height: Height,
+    /// Whether a tree exactly as high as the observer blocks the view
+    equal_blocks: bool,
 }
 
-fn is_visible_down(input: &Vec<Vec<u8>>, height: Option<u8>, x: usize, y: usize) -> bool {
-    let current = input[y][x];
-    if let Some(h) = height {
-        if current >= h {
-            return false;
+impl SightRules {
+    /// Looking along the four cardinal directions, blocked by any tree at least as tall
+    fn puzzle() -> Self {
+        Self {
+            directions: Direction::CARDINALS.to_vec(),
+            max_distance: None,
+            observer_height: 0,
+            equal_blocks: true,
         }
     }
 
-    if y == input.len() - 1 {
-        return true;
+    fn blocks(&self, tree: Height, observer: Height) -> bool {
+        if self.equal_blocks {
+            tree >= observer
+        } else {
+            tree > observer
+        }
     }
+}
 
-    is_visible_down(input, Some(height.unwrap_or(current)), x, y + 1)
+#[derive(Debug, Clone, Copy, Default)]
+struct Sight {
+    /// Number of trees seen before the view is blocked or reaches the edge
+    distance: u32,
+    /// Whether nothing blocks the view up to the edge of the grid
+    sees_edge: bool,
 }
 
-fn is_visible_left(input: &Vec<Vec<u8>>, height: Option<u8>, x: usize, y: usize) -> bool {
-    let current = input[y][x];
-    if let Some(h) = height {
-        if current >= h {
-            return false;
+/// Line of sight of every tree towards `dir`. Each line is swept with a stack of the trees that
+/// can still block a view : shorter trees are hidden behind the current one and dropped, so
+/// heights strictly decrease from the bottom of the stack and the closest blocker of any height
+/// is found with a binary search
+fn sight_lines(input: &[Vec<Height>], dir: Direction, rules: &SightRules) -> Vec<Vec<Sight>> {
+    let mut sights = vec![vec![Sight::default(); input[0].len()]; input.len()];
+
+    for line in dir.lines(input) {
+        let mut stack: Vec<(usize, Height)> = vec![];
+        for (i, (x, y)) in line.iter().copied().enumerate() {
+            let current = input[y][x];
+            let observer = current + rules.observer_height;
+
+            let blocking = stack.partition_point(|(_, h)| rules.blocks(*h, observer));
+            let blocker = blocking.checked_sub(1).map(|b| stack[b].0);
+
+            let distance = i - blocker.unwrap_or(0);
+            let within_reach = rules.max_distance.is_none_or(|max| i <= max);
+            sights[y][x] = Sight {
+                distance: rules.max_distance.map_or(distance, |max| distance.min(max)) as u32,
+                sees_edge: blocker.is_none() && within_reach,
+            };
+
+            while stack.last().is_some_and(|(_, h)| *h <= current) {
+                stack.pop();
+            }
+            stack.push((i, current));
         }
     }
 
-    if x == 0 {
-        return true;
+    sights
+}
+
+/// Trees seeing the edge of the grid, hence visible from outside, along any of the directions
+fn visibility_map(input: &[Vec<Height>], rules: &SightRules) -> Vec<Vec<bool>> {
+    let mut visible = vec![vec![false; input[0].len()]; input.len()];
+
+    for dir in &rules.directions {
+        for (y, row) in sight_lines(input, *dir, rules).iter().enumerate() {
+            for (x, sight) in row.iter().enumerate() {
+                visible[y][x] |= sight.sees_edge;
+            }
+        }
     }
 
-    is_visible_left(input, Some(height.unwrap_or(current)), x - 1, y)
+    visible
+}
+
+fn part1(input: &[Vec<Height>], rules: &SightRules) -> u32 {
+    visibility_map(input, rules)
+        .iter()
+        .flatten()
+        .filter(|visible| **visible)
+        .count() as u32
+}
+
+fn view_distances(input: &[Vec<Height>], dir: Direction, rules: &SightRules) -> Vec<Vec<u32>> {
+    sight_lines(input, dir, rules)
+        .into_iter()
+        .map(|row| row.into_iter().map(|sight| sight.distance).collect())
+        .collect()
+}
+
+fn scenic_scores(input: &[Vec<Height>], rules: &SightRules) -> Vec<Vec<u64>> {
+    let distances = rules
+        .directions
+        .iter()
+        .map(|dir| view_distances(input, *dir, rules))
+        .collect::<Vec<_>>();
+
+    (0..input.len())
+        .map(|y| {
+            (0..input[0].len())
+                .map(|x| distances.iter().map(|d| d[y][x] as u64).product())
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Treehouse {
+    x: usize,
+    y: usize,
+    height: Height,
+    score: u64,
+    distances: Vec<(Direction, u32)>,
 }
 
-fn is_visible_right(input: &Vec<Vec<u8>>, height: Option<u8>, x: usize, y: usize) -> bool {
-    let current = input[y][x];
-    if let Some(h) = height {
-        if current >= h {
-            return false;
+/// Which trees can host the treehouse, every criteria left to `None` accepting any tree
+#[derive(Debug)]
+struct TreehouseQuery {
+    top: usize,
+    min_height: Option<Height>,
+    /// Minimum number of trees between the candidate and the closest edge
+    min_edge_distance: Option<usize>,
+    /// Inclusive `(x, y)` corners of the area to search
+    area: Option<((usize, usize), (usize, usize))>,
+}
+
+impl Default for TreehouseQuery {
+    fn default() -> Self {
+        Self {
+            top: 1,
+            min_height: None,
+            min_edge_distance: None,
+            area: None,
         }
     }
+}
+
+impl TreehouseQuery {
+    fn accepts(&self, input: &[Vec<Height>], (x, y): (usize, usize)) -> bool {
+        let (width, height) = (input[0].len(), input.len());
+        let edge_distance = x.min(y).min(width - 1 - x).min(height - 1 - y);
 
-    if x == input[0].len() - 1 {
-        return true;
+        self.min_height.is_none_or(|h| input[y][x] >= h)
+            && self.min_edge_distance.is_none_or(|d| edge_distance >= d)
+            && self.area.is_none_or(|((x0, y0), (x1, y1))| {
+                (x0.min(x1)..=x0.max(x1)).contains(&x) && (y0.min(y1)..=y0.max(y1)).contains(&y)
+            })
     }
+}
+
+/// The `query.top` trees with the best scenic score, best first and in reading order on ties
+fn best_treehouses(
+    input: &[Vec<Height>],
+    rules: &SightRules,
+    query: &TreehouseQuery,
+) -> Vec<Treehouse> {
+    let distances = rules
+        .directions
+        .iter()
+        .map(|dir| (*dir, view_distances(input, *dir, rules)))
+        .collect::<Vec<_>>();
+
+    let mut candidates = (0..input.len())
+        .flat_map(|y| (0..input[0].len()).map(move |x| (x, y)))
+        .filter(|coordinates| query.accepts(input, *coordinates))
+        .map(|(x, y)| {
+            let distances = distances
+                .iter()
+                .map(|(dir, d)| (*dir, d[y][x]))
+                .collect::<Vec<_>>();
+            Treehouse {
+                x,
+                y,
+                height: input[y][x],
+                score: distances.iter().map(|(_, d)| *d as u64).product(),
+                distances,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| (a.y, a.x).cmp(&(b.y, b.x)))
+    });
+    candidates.truncate(query.top);
+    candidates
+}
 
-    is_visible_right(input, Some(height.unwrap_or(current)), x + 1, y)
+fn part2(input: &[Vec<Height>], rules: &SightRules) -> u64 {
+    best_treehouses(input, rules, &TreehouseQuery::default())
+        .first()
+        .map_or(0, |treehouse| treehouse.score)
+}
+
+fn render_visibility_map(visible: &[Vec<bool>]) -> String {
+    visible
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| if *v { '#' } else { '.' })
+                .collect::<String>()
+                + "\n"
+        })
+        .collect()
 }
 
-fn is_tree_visible(input: &Vec<Vec<u8>>, x: usize, y: usize) -> bool {
-    is_visible_up(input, None, x, y)
-        || is_visible_down(input, None, x, y)
-        || is_visible_left(input, None, x, y)
-        || is_visible_right(input, None, x, y)
+type Rgb = [u8; 3];
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
 }
 
-fn part1(input: &Vec<Vec<u8>>) -> u32 {
-    let mut count = 0_u32;
-    for y in 0..input.len() {
-        for x in 0..input[0].len() {
-            if is_tree_visible(input, x, y) {
-                count += 1;
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    fn fill(&mut self, (x, y): (usize, usize), (width, height): (usize, usize), color: Rgb) {
+        for py in y..y + height {
+            for px in x..x + width {
+                self.set(px, py, color);
             }
         }
     }
-    count
+
+    /// Binary PPM, or PGM when every pixel is grey
+    fn write(&self, path: &str) -> std::io::Result<()> {
+        let grey = self.pixels.iter().all(|[r, g, b]| r == g && g == b);
+        let mut data = format!(
+            "{}\n{} {}\n255\n",
+            if grey { "P5" } else { "P6" },
+            self.width,
+            self.height
+        )
+        .into_bytes();
+
+        for pixel in &self.pixels {
+            if grey {
+                data.push(pixel[0]);
+            } else {
+                data.extend_from_slice(pixel);
+            }
+        }
+        std::fs::write(path, data)
+    }
 }
 
-enum Direction {
-    North,
-    South,
-    West,
-    East,
+/// Darkens `base` for short trees, `t` being the height relative to the forest in `[0, 1]`
+fn shade(base: Rgb, t: f64) -> Rgb {
+    let factor = 0.3 + 0.7 * t.clamp(0.0, 1.0);
+    base.map(|c| (c as f64 * factor) as u8)
 }
 
-impl Direction {
-    fn move_coordinates(&self, (mut x, mut y): (usize, usize)) -> (usize, usize) {
-        match self {
-            Direction::North => y -= 1,
-            Direction::South => y += 1,
-            Direction::West => x -= 1,
-            Direction::East => x += 1,
-        };
+/// Visible trees in green and hidden ones in grey, both brighter the taller they are
+fn render_visibility_image(input: &[Vec<Height>], visible: &[Vec<bool>], scale: usize) -> Image {
+    let mut image = Image::new(input[0].len() * scale, input.len() * scale);
+    let min = input.iter().flatten().copied().min().unwrap_or(0);
+    let max = input.iter().flatten().copied().max().unwrap_or(0);
+    let range = (max - min).max(1) as f64;
 
-        (x, y)
+    for (y, row) in input.iter().enumerate() {
+        for (x, height) in row.iter().enumerate() {
+            let base = if visible[y][x] {
+                [60, 220, 80]
+            } else {
+                [110, 110, 110]
+            };
+            image.fill(
+                (x * scale, y * scale),
+                (scale, scale),
+                shade(base, (height - min) as f64 / range),
+            );
+        }
     }
 
-    fn is_edge(&self, input: &Vec<Vec<u8>>, (x, y): (usize, usize)) -> bool {
-        match self {
-            Direction::North => y == 0,
-            Direction::South => y == input.len() - 1,
-            Direction::West => x == 0,
-            Direction::East => x == input[0].len() - 1,
+    image
+}
+
+/// Black and white mask of the visible trees, written as a PGM
+fn render_visibility_mask(visible: &[Vec<bool>], scale: usize) -> Image {
+    let mut image = Image::new(visible[0].len() * scale, visible.len() * scale);
+
+    for (y, row) in visible.iter().enumerate() {
+        for (x, v) in row.iter().enumerate() {
+            if *v {
+                image.fill((x * scale, y * scale), (scale, scale), [255, 255, 255]);
+            }
         }
     }
+
+    image
 }
 
-fn get_view_distance_impl(
-    input: &Vec<Vec<u8>>,
-    dir: Direction,
-    h: Option<u8>,
-    x: usize,
-    y: usize,
-    vd: &mut u32,
-) {
-    let current = input[y][x];
-    if let Some(h) = h {
-        if current >= h {
-            return;
+const HEATMAP_SCALE: [Rgb; 5] = [
+    [20, 20, 90],
+    [30, 140, 200],
+    [60, 200, 90],
+    [240, 220, 40],
+    [220, 40, 30],
+];
+
+/// Maps `t` in `[0, 1]` on the heatmap colour scale
+fn heat_color(t: f64) -> Rgb {
+    let t = t.clamp(0.0, 1.0) * (HEATMAP_SCALE.len() - 1) as f64;
+    let i = (t.floor() as usize).min(HEATMAP_SCALE.len() - 2);
+    let f = t - i as f64;
+    let (a, b) = (HEATMAP_SCALE[i], HEATMAP_SCALE[i + 1]);
+    [0, 1, 2].map(|c| (a[c] as f64 + (b[c] as f64 - a[c] as f64) * f) as u8)
+}
+
+/// Scenic scores on a logarithmic colour scale shown below the map, the best tree outlined
+/// in white
+fn render_heatmap(scores: &[Vec<u64>], scale: usize) -> Image {
+    let (width, height) = (scores[0].len(), scores.len());
+    let legend = (scale * 2).max(4);
+    let mut image = Image::new(width * scale, height * scale + legend);
+
+    let max = scores.iter().flatten().copied().max().unwrap_or(0);
+    let normalize = |score: u64| (score as f64).ln_1p() / (max as f64).ln_1p().max(f64::EPSILON);
+
+    let mut best = (0, 0);
+    for (y, row) in scores.iter().enumerate() {
+        for (x, score) in row.iter().enumerate() {
+            if *score == max && scores[best.1][best.0] != max {
+                best = (x, y);
+            }
+            image.fill(
+                (x * scale, y * scale),
+                (scale, scale),
+                heat_color(normalize(*score)),
+            );
         }
     }
 
-    if dir.is_edge(input, (x, y)) {
-        return;
+    for x in 0..image.width {
+        let color = heat_color(x as f64 / (image.width - 1).max(1) as f64);
+        image.fill((x, height * scale), (1, legend), color);
     }
 
-    *vd += 1;
-    let (x, y) = dir.move_coordinates((x, y));
-    get_view_distance_impl(input, dir, Some(h.unwrap_or(current)), x, y, vd)
+    let (bx, by) = (best.0 * scale, best.1 * scale);
+    let white = [255, 255, 255];
+    let border = (scale / 4).max(1);
+    image.fill((bx, by), (scale, border), white);
+    image.fill((bx, by + scale - border), (scale, border), white);
+    image.fill((bx, by), (border, scale), white);
+    image.fill((bx + scale - border, by), (border, scale), white);
+
+    image
 }
 
-fn get_view_distance(input: &Vec<Vec<u8>>, dir: Direction, (x, y): (usize, usize)) -> u32 {
-    let mut vd = 0_u32;
-    get_view_distance_impl(input, dir, None, x, y, &mut vd);
-    vd
+enum Render {
+    Map,
+    Visibility(String),
+    Mask(String),
+    Heatmap(String),
+    Top,
 }
 
-fn part2(input: &Vec<Vec<u8>>) -> u32 {
-    let mut max_score = 0_u32;
-    for y in 0..input.len() {
-        for x in 0..input[0].len() {
-            let n = get_view_distance(input, Direction::North, (x, y));
-            let s = get_view_distance(input, Direction::South, (x, y));
-            let w = get_view_distance(input, Direction::West, (x, y));
-            let e = get_view_distance(input, Direction::East, (x, y));
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut scale = 8;
+    let mut path = None;
+    let mut renders = vec![];
+    let mut part1_rules = SightRules::puzzle();
+    let mut part2_rules = SightRules::puzzle();
+    let mut query = TreehouseQuery::default();
+    let mut format = HeightFormat::Detect;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .expect("--scale expects a positive number of pixels per tree")
+            }
+            "--diagonals" => {
+                part1_rules.directions = Direction::ALL.to_vec();
+                part2_rules.directions = Direction::ALL.to_vec();
+            }
+            "--max-distance" => {
+                let max = args
+                    .next()
+                    .and_then(|m| m.parse().ok())
+                    .expect("--max-distance expects a number of trees");
+                part1_rules.max_distance = Some(max);
+                part2_rules.max_distance = Some(max);
+            }
+            "--observer" => {
+                let height = args
+                    .next()
+                    .and_then(|h| h.parse().ok())
+                    .expect("--observer expects a height");
+                part1_rules.observer_height = height;
+                part2_rules.observer_height = height;
+            }
+            "--equal-visible" => {
+                part1_rules.equal_blocks = false;
+                part2_rules.equal_blocks = false;
+            }
+            "--top" => {
+                query.top = args
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .expect("--top expects a number of trees");
+                renders.push(Render::Top);
+            }
+            "--min-height" => {
+                query.min_height = Some(
+                    args.next()
+                        .and_then(|h| h.parse().ok())
+                        .expect("--min-height expects a height"),
+                )
+            }
+            "--min-edge-distance" => {
+                query.min_edge_distance = Some(
+                    args.next()
+                        .and_then(|d| d.parse().ok())
+                        .expect("--min-edge-distance expects a number of trees"),
+                )
+            }
+            "--area" => {
+                let area = args
+                    .next()
+                    .and_then(|area| {
+                        area.split(',')
+                            .map(|c| c.trim().parse().ok())
+                            .collect::<Option<Vec<usize>>>()
+                    })
+                    .filter(|c| c.len() == 4)
+                    .expect("--area expects x0,y0,x1,y1");
+                query.area = Some(((area[0], area[1]), (area[2], area[3])));
+            }
+            "--digits" => format = HeightFormat::Digits,
+            "--integers" => format = HeightFormat::Integers,
+            "--map" => renders.push(Render::Map),
+            "--visibility" => renders.push(Render::Visibility(
+                args.next().expect("--visibility expects an output path"),
+            )),
+            "--mask" => renders.push(Render::Mask(
+                args.next().expect("--mask expects an output path"),
+            )),
+            "--heatmap" => renders.push(Render::Heatmap(
+                args.next().expect("--heatmap expects an output path"),
+            )),
+            _ if arg.starts_with("--") => panic!("Unexpected argument '{arg}'"),
+            _ => path = Some(arg),
+        }
+    }
+
+    // Filters on their own list the single best treehouse matching them
+    let filtered =
+        query.min_height.is_some() || query.min_edge_distance.is_some() || query.area.is_some();
+    if filtered && !renders.iter().any(|r| matches!(r, Render::Top)) {
+        renders.push(Render::Top);
+    }
+
+    let input = match path {
+        Some(path) => std::fs::read_to_string(path).expect("Failed to read height map"),
+        None => include_str!("day8.txt").to_string(),
+    };
+    let input = parse_input(&input, format).expect("Invalid height map");
 
-            let score = n * s * w * e;
-            if score > max_score {
-                max_score = score;
+    for render in &renders {
+        match render {
+            Render::Map => print!(
+                "{}",
+                render_visibility_map(&visibility_map(&input, &part1_rules))
+            ),
+            Render::Visibility(path) => {
+                render_visibility_image(&input, &visibility_map(&input, &part1_rules), scale)
+                    .write(path)
+                    .expect("Failed to write the visibility image")
+            }
+            Render::Mask(path) => {
+                render_visibility_mask(&visibility_map(&input, &part1_rules), scale)
+                    .write(path)
+                    .expect("Failed to write the visibility mask")
+            }
+            Render::Heatmap(path) => render_heatmap(&scenic_scores(&input, &part2_rules), scale)
+                .write(path)
+                .expect("Failed to write the heatmap"),
+            Render::Top => {
+                for (rank, treehouse) in best_treehouses(&input, &part2_rules, &query)
+                    .iter()
+                    .enumerate()
+                {
+                    let distances = treehouse
+                        .distances
+                        .iter()
+                        .map(|(dir, d)| format!("{dir:?}={d}"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    println!(
+                        "#{} ({}, {}) height {} score {} : {distances}",
+                        rank + 1,
+                        treehouse.x,
+                        treehouse.y,
+                        treehouse.height,
+                        treehouse.score
+                    );
+                }
             }
         }
     }
+    if !renders.is_empty() {
+        return;
+    }
 
-    max_score
+    println!("part 1 : {}", part1(&input, &part1_rules));
+    println!("part 2 : {}", part2(&input, &part2_rules));
 }
 
-fn main() {
-    let input = include_str!("day8.txt");
-    let input = parse_input(input);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visibility(input: &[Vec<Height>]) -> String {
+        render_visibility_map(&visibility_map(input, &SightRules::puzzle()))
+    }
+
+    #[test]
+    fn wide_grid() {
+        let input = parse_input("9999\n9119\n9999\n", HeightFormat::Detect).unwrap();
+        assert_eq!(input.len(), 3);
+        assert!(input.iter().all(|row| row.len() == 4));
+        assert_eq!(visibility(&input), "####\n#..#\n####\n");
+        assert_eq!(part1(&input, &SightRules::puzzle()), 10);
+        assert_eq!(part2(&input, &SightRules::puzzle()), 1);
+    }
+
+    #[test]
+    fn tall_grid() {
+        let input = parse_input("999\n919\n929\n999\n", HeightFormat::Detect).unwrap();
+        assert_eq!(input.len(), 4);
+        assert!(input.iter().all(|row| row.len() == 3));
+        assert_eq!(visibility(&input), "###\n#.#\n#.#\n###\n");
+        assert_eq!(part1(&input, &SightRules::puzzle()), 10);
+        assert_eq!(part2(&input, &SightRules::puzzle()), 2);
+    }
+
+    #[test]
+    fn multi_digit_heights() {
+        let input = parse_input("10 200 3\n 4   5 6\n", HeightFormat::Detect).unwrap();
+        assert_eq!(input, vec![vec![10, 200, 3], vec![4, 5, 6]]);
+
+        let column = parse_input("12\n34\n56", HeightFormat::Integers).unwrap();
+        assert_eq!(column, vec![vec![12], vec![34], vec![56]]);
+        let digits = parse_input("12\n34\n56", HeightFormat::Detect).unwrap();
+        assert_eq!(digits, vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+    }
+
+    #[test]
+    fn ragged_rows_are_rejected() {
+        let error = parse_input("123\n12\n123", HeightFormat::Detect).unwrap_err();
+        assert!(error.to_string().contains("line 2 has 2 trees"), "{error}");
+
+        // The format is chosen once, so a row of digits among integers is a single tree
+        let error = parse_input("1 2 3\n456", HeightFormat::Detect).unwrap_err();
+        assert!(error.to_string().contains("line 2 has 1 trees"), "{error}");
+    }
+
+    /// Recursive implementation the linear sweeps replaced, kept as a reference
+    mod recursive {
+        use super::Height;
+
+        fn is_visible_up(
+            input: &[Vec<Height>],
+            height: Option<Height>,
+            x: usize,
+            y: usize,
+        ) -> bool {
+            let current = input[y][x];
+            if let Some(h) = height {
+                if current >= h {
+                    return false;
+                }
+            }
+
+            if y == 0 {
+                return true;
+            }
+
+            is_visible_up(input, Some(height.unwrap_or(current)), x, y - 1)
+        }
+
+        fn is_visible_down(
+            input: &[Vec<Height>],
+            height: Option<Height>,
+            x: usize,
+            y: usize,
+        ) -> bool {
+            let current = input[y][x];
+            if let Some(h) = height {
+                if current >= h {
+                    return false;
+                }
+            }
+
+            if y == input.len() - 1 {
+                return true;
+            }
+
+            is_visible_down(input, Some(height.unwrap_or(current)), x, y + 1)
+        }
+
+        fn is_visible_left(
+            input: &[Vec<Height>],
+            height: Option<Height>,
+            x: usize,
+            y: usize,
+        ) -> bool {
+            let current = input[y][x];
+            if let Some(h) = height {
+                if current >= h {
+                    return false;
+                }
+            }
+
+            if x == 0 {
+                return true;
+            }
+
+            is_visible_left(input, Some(height.unwrap_or(current)), x - 1, y)
+        }
+
+        fn is_visible_right(
+            input: &[Vec<Height>],
+            height: Option<Height>,
+            x: usize,
+            y: usize,
+        ) -> bool {
+            let current = input[y][x];
+            if let Some(h) = height {
+                if current >= h {
+                    return false;
+                }
+            }
 
-    println!("part 1 : {}", part1(&input));
-    println!("part 2 : {}", part2(&input));
+            if x == input[0].len() - 1 {
+                return true;
+            }
+
+            is_visible_right(input, Some(height.unwrap_or(current)), x + 1, y)
+        }
+
+        pub fn is_tree_visible(input: &[Vec<Height>], x: usize, y: usize) -> bool {
+            is_visible_up(input, None, x, y)
+                || is_visible_down(input, None, x, y)
+                || is_visible_left(input, None, x, y)
+                || is_visible_right(input, None, x, y)
+        }
+
+        #[derive(Clone, Copy)]
+        pub enum Direction {
+            North,
+            South,
+            West,
+            East,
+        }
+
+        impl Direction {
+            fn move_coordinates(&self, (mut x, mut y): (usize, usize)) -> (usize, usize) {
+                match self {
+                    Direction::North => y -= 1,
+                    Direction::South => y += 1,
+                    Direction::West => x -= 1,
+                    Direction::East => x += 1,
+                };
+
+                (x, y)
+            }
+
+            fn is_edge(&self, input: &[Vec<Height>], (x, y): (usize, usize)) -> bool {
+                match self {
+                    Direction::North => y == 0,
+                    Direction::South => y == input.len() - 1,
+                    Direction::West => x == 0,
+                    Direction::East => x == input[0].len() - 1,
+                }
+            }
+        }
+
+        fn get_view_distance_impl(
+            input: &[Vec<Height>],
+            dir: Direction,
+            h: Option<Height>,
+            x: usize,
+            y: usize,
+            vd: &mut u32,
+        ) {
+            let current = input[y][x];
+            if let Some(h) = h {
+                if current >= h {
+                    return;
+                }
+            }
+
+            if dir.is_edge(input, (x, y)) {
+                return;
+            }
+
+            *vd += 1;
+            let (x, y) = dir.move_coordinates((x, y));
+            get_view_distance_impl(input, dir, Some(h.unwrap_or(current)), x, y, vd)
+        }
+
+        pub fn get_view_distance(
+            input: &[Vec<Height>],
+            dir: Direction,
+            (x, y): (usize, usize),
+        ) -> u32 {
+            let mut vd = 0_u32;
+            get_view_distance_impl(input, dir, None, x, y, &mut vd);
+            vd
+        }
+    }
+
+    /// Grids from 1x1 to 12x12 with heights up to `max_height`, generated with a fixed seed
+    fn random_grids(max_height: u64) -> Vec<Vec<Vec<Height>>> {
+        let mut seed = 0x0808_u64;
+        let mut next = |bound: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+
+        (0..200)
+            .map(|_| {
+                let (width, height) = (1 + next(12), 1 + next(12));
+                (0..height)
+                    .map(|_| (0..width).map(|_| next(max_height + 1) as Height).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sweeps_match_the_recursive_implementation() {
+        let rules = SightRules::puzzle();
+        let directions = [
+            (Direction::North, recursive::Direction::North),
+            (Direction::South, recursive::Direction::South),
+            (Direction::West, recursive::Direction::West),
+            (Direction::East, recursive::Direction::East),
+        ];
+
+        for input in random_grids(9).into_iter().chain(random_grids(2)) {
+            let visible = visibility_map(&input, &rules);
+            let distances = directions.map(|(dir, _)| view_distances(&input, dir, &rules));
+
+            for y in 0..input.len() {
+                for x in 0..input[0].len() {
+                    assert_eq!(
+                        visible[y][x],
+                        recursive::is_tree_visible(&input, x, y),
+                        "{input:?} at ({x}, {y})"
+                    );
+                    for (d, (_, old)) in directions.iter().enumerate() {
+                        assert_eq!(
+                            distances[d][y][x],
+                            recursive::get_view_distance(&input, *old, (x, y)),
+                            "{input:?} at ({x}, {y})"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn grid(rows: &str) -> Vec<Vec<Height>> {
+        parse_input(rows, HeightFormat::Digits).unwrap()
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let input = grid("30373\n25512\n65332\n33549\n35390");
+        assert_eq!(part1(&input, &SightRules::puzzle()), 21);
+        assert_eq!(part2(&input, &SightRules::puzzle()), 8);
+    }
+
+    #[test]
+    fn diagonals() {
+        let input = grid("191\n959\n191");
+        let diagonals = SightRules {
+            directions: Direction::ALL.to_vec(),
+            ..SightRules::puzzle()
+        };
+        assert_eq!(visibility(&input), "###\n#.#\n###\n");
+        assert_eq!(
+            render_visibility_map(&visibility_map(&input, &diagonals)),
+            "###\n###\n###\n"
+        );
+        assert_eq!(
+            view_distances(&input, Direction::SouthEast, &diagonals)[1][1],
+            1
+        );
+        // Corners stop at the center, or see nothing past the edge
+        assert_eq!(
+            view_distances(&input, Direction::NorthWest, &diagonals)[2][2],
+            1
+        );
+        assert_eq!(
+            view_distances(&input, Direction::NorthEast, &diagonals)[2][0],
+            1
+        );
+        assert_eq!(
+            view_distances(&input, Direction::NorthWest, &diagonals)[0][0],
+            0
+        );
+    }
+
+    #[test]
+    fn max_distance() {
+        let input = grid("9999999\n1234321\n9999999");
+        let near = SightRules {
+            max_distance: Some(2),
+            ..SightRules::puzzle()
+        };
+        assert_eq!(visibility(&input), "#######\n#######\n#######\n");
+        assert_eq!(
+            render_visibility_map(&visibility_map(&input, &near)),
+            "#######\n###.###\n#######\n"
+        );
+        assert_eq!(
+            view_distances(&input, Direction::West, &SightRules::puzzle())[1][3],
+            3
+        );
+        assert_eq!(view_distances(&input, Direction::West, &near)[1][3], 2);
+        assert_eq!(view_distances(&input, Direction::West, &near)[1][1], 1);
+    }
+
+    #[test]
+    fn observer_height_and_equal_trees() {
+        let input = grid("161\n656\n161");
+        let center = |rules: SightRules| visibility_map(&input, &rules)[1][1];
+
+        assert!(!center(SightRules::puzzle()));
+        assert!(!center(SightRules {
+            observer_height: 1,
+            ..SightRules::puzzle()
+        }));
+        assert!(center(SightRules {
+            observer_height: 2,
+            ..SightRules::puzzle()
+        }));
+        assert!(center(SightRules {
+            observer_height: 1,
+            equal_blocks: false,
+            ..SightRules::puzzle()
+        }));
+
+        let flat = grid("55555\n55555\n55555\n55555\n55555");
+        let equal_visible = SightRules {
+            equal_blocks: false,
+            ..SightRules::puzzle()
+        };
+        assert_eq!(part1(&flat, &SightRules::puzzle()), 16);
+        assert_eq!(part1(&flat, &equal_visible), 25);
+        assert_eq!(
+            view_distances(&flat, Direction::North, &SightRules::puzzle())[2][2],
+            1
+        );
+        assert_eq!(
+            view_distances(&flat, Direction::North, &equal_visible)[2][2],
+            2
+        );
+    }
+
+    fn treehouses(query: TreehouseQuery) -> Vec<(usize, usize)> {
+        let input = grid("30373\n25512\n65332\n33549\n35390");
+        best_treehouses(&input, &SightRules::puzzle(), &query)
+            .iter()
+            .map(|treehouse| (treehouse.x, treehouse.y))
+            .collect()
+    }
+
+    #[test]
+    fn treehouse_order_and_top() {
+        // Scores 8, 6, 4, 3, 2, then 1 for the last two, in reading order
+        let best = treehouses(TreehouseQuery {
+            top: 7,
+            ..Default::default()
+        });
+        assert_eq!(
+            best,
+            [(2, 3), (1, 2), (2, 1), (3, 3), (3, 2), (1, 1), (3, 1)]
+        );
+        assert_eq!(treehouses(TreehouseQuery::default()), [(2, 3)]);
+        assert_eq!(
+            treehouses(TreehouseQuery {
+                top: 100,
+                ..Default::default()
+            })
+            .len(),
+            25
+        );
+    }
+
+    #[test]
+    fn treehouse_filters() {
+        let tall = treehouses(TreehouseQuery {
+            top: 100,
+            min_height: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(tall.len(), 9);
+        assert_eq!(tall[..4], [(2, 3), (1, 2), (2, 1), (1, 1)]);
+
+        let inner = treehouses(TreehouseQuery {
+            top: 100,
+            min_edge_distance: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(inner.len(), 9);
+        let center = treehouses(TreehouseQuery {
+            top: 100,
+            min_edge_distance: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(center, [(2, 2)]);
+
+        let area = treehouses(TreehouseQuery {
+            top: 100,
+            area: Some(((3, 3), (2, 2))),
+            ..Default::default()
+        });
+        assert_eq!(area, [(2, 3), (3, 3), (3, 2), (2, 2)]);
+    }
 }
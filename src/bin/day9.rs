@@ -1,13 +1,33 @@
-use std::str::FromStr;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use anyhow::anyhow;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Direction {
     Left,
     Up,
     Right,
     Down,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    /// Step along the x and y axes, y going up
+    fn components(&self) -> (i64, i64) {
+        match self {
+            Self::Left => (-1, 0),
+            Self::Up => (0, 1),
+            Self::Right => (1, 0),
+            Self::Down => (0, -1),
+            Self::UpLeft => (-1, 1),
+            Self::UpRight => (1, 1),
+            Self::DownLeft => (-1, -1),
+            Self::DownRight => (1, -1),
+        }
+    }
 }
 
 impl FromStr for Direction {
@@ -19,15 +39,35 @@ impl FromStr for Direction {
             "U" => Self::Up,
             "R" => Self::Right,
             "D" => Self::Down,
+            "UL" => Self::UpLeft,
+            "UR" => Self::UpRight,
+            "DL" => Self::DownLeft,
+            "DR" => Self::DownRight,
             _ => {
                 return Err(anyhow!(
-                    "Invalid direction, expected one of L, U, R, D but found {s}"
+                    "Invalid direction, expected one of L, U, R, D, UL, UR, DL, DR but found {s}"
                 ))
             }
         })
     }
 }
 
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self {
+            Self::Left => "L",
+            Self::Up => "U",
+            Self::Right => "R",
+            Self::Down => "D",
+            Self::UpLeft => "UL",
+            Self::UpRight => "UR",
+            Self::DownLeft => "DL",
+            Self::DownRight => "DR",
+        };
+        write!(f, "{direction}")
+    }
+}
+
 #[derive(Debug)]
 struct Movement {
     direction: Direction,
@@ -44,128 +84,388 @@ impl FromStr for Movement {
 
         Ok(Self {
             direction: direction.parse()?,
-            amount: amount.parse()?,
+            amount: amount
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid amount '{amount}' : {e}"))?,
         })
     }
 }
 
+impl Display for Movement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.direction, self.amount)
+    }
+}
+
+/// Instruction of a movement script
+#[derive(Debug)]
+enum Instruction {
+    Move(Movement),
+    /// Body run the given number of times
+    Repeat(u32, Vec<Instruction>),
+}
+
 #[derive(Debug)]
 struct MovementList {
-    list: Vec<Movement>,
+    script: Vec<Instruction>,
+}
+
+impl MovementList {
+    /// Movements in order, repeat blocks being expanded as they are reached rather than held in
+    /// memory
+    fn movements(&self) -> impl Iterator<Item = &Movement> {
+        expand(&self.script)
+    }
+}
+
+fn expand(script: &[Instruction]) -> Box<dyn Iterator<Item = &Movement> + '_> {
+    Box::new(script.iter().flat_map(|instruction| match instruction {
+        Instruction::Move(movement) => {
+            Box::new(std::iter::once(movement)) as Box<dyn Iterator<Item = &Movement>>
+        }
+        Instruction::Repeat(count, body) => Box::new((0..*count).flat_map(move |_| expand(body))),
+    }))
 }
 
 impl FromStr for MovementList {
     type Err = anyhow::Error;
 
+    /// Movements such as `R 4` or `UL 2`, separated by new lines or `;`, and `repeat N { ... }`
+    /// blocks, which may be nested and either fit on one line or span several. `#` starts a
+    /// comment running to the end of the line
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let list = s.lines().flat_map(|l| l.parse()).collect();
-        Ok(Self { list })
+        let mut script = vec![];
+        // Repeat count, opening line and instructions of the enclosing script for every open block
+        let mut blocks: Vec<(u32, usize, Vec<Instruction>)> = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let mut rest = line.split_once('#').map_or(line, |(code, _)| code).trim();
+
+            while !rest.is_empty() {
+                if let Some(repeat) = rest.strip_prefix("repeat ") {
+                    let (count, body) = repeat.split_once('{').ok_or(anyhow!(
+                        "line {line_number} : expected '{{' after the repeat count"
+                    ))?;
+                    let count = count.trim();
+                    let count = count.parse().map_err(|e| {
+                        anyhow!("line {line_number} : invalid repeat count '{count}' : {e}")
+                    })?;
+                    blocks.push((count, line_number, std::mem::take(&mut script)));
+                    rest = body;
+                } else if let Some(after) = rest.strip_prefix('}') {
+                    let (count, _, outer) = blocks.pop().ok_or(anyhow!(
+                        "line {line_number} : '}}' without an open repeat block"
+                    ))?;
+                    let body = std::mem::replace(&mut script, outer);
+                    script.push(Instruction::Repeat(count, body));
+                    rest = after;
+                } else {
+                    let end = rest.find([';', '}']).unwrap_or(rest.len());
+                    let movement = rest[..end]
+                        .trim()
+                        .parse()
+                        .map_err(|e| anyhow!("line {line_number} : {e}"))?;
+                    script.push(Instruction::Move(movement));
+                    rest = &rest[end..];
+                    rest = rest.strip_prefix(';').unwrap_or(rest);
+                }
+                rest = rest.trim_start();
+            }
+        }
+
+        if let Some((_, line_number, _)) = blocks.last() {
+            return Err(anyhow!("line {line_number} : repeat block is never closed"));
+        }
+        Ok(Self { script })
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
 struct Position {
     x: i64,
     y: i64,
 }
 
-#[derive(Debug)]
-enum CatchUpMovement {
-    None,
-    One(Direction),
-    Diagonal(Direction, Direction),
-}
-
-impl CatchUpMovement {
-    fn from_head_tail(head: &Position, tail: &Position) -> Self {
-        if head.x == tail.x {
-            if head.y > tail.y + 1 {
-                Self::One(Direction::Up)
-            } else if head.y < tail.y - 1 {
-                Self::One(Direction::Down)
-            } else {
-                Self::None
-            }
-        } else if head.y == tail.y {
-            if head.x > tail.x + 1 {
-                Self::One(Direction::Right)
-            } else if head.x < tail.x - 1 {
-                Self::One(Direction::Left)
-            } else {
-                Self::None
-            }
-        } else if head.x > tail.x + 1 && head.y >= tail.y + 1
-            || head.x >= tail.x + 1 && head.y > tail.y + 1
-        {
-            Self::Diagonal(Direction::Up, Direction::Right)
-        } else if head.x > tail.x + 1 && head.y <= tail.y - 1
-            || head.x >= tail.x + 1 && head.y < tail.y - 1
-        {
-            Self::Diagonal(Direction::Down, Direction::Right)
-        } else if head.x < tail.x - 1 && head.y >= tail.y + 1
-            || head.x <= tail.x - 1 && head.y > tail.y + 1
-        {
-            Self::Diagonal(Direction::Up, Direction::Left)
-        } else if head.x < tail.x - 1 && head.y <= tail.y - 1
-            || head.x <= tail.x - 1 && head.y < tail.y - 1
-        {
-            Self::Diagonal(Direction::Down, Direction::Left)
-        } else {
-            Self::None
-        }
-    }
-
-    fn apply(&self, tail: &mut Position) {
-        match self {
-            CatchUpMovement::None => {}
-            CatchUpMovement::One(d) => tail.move_to_direction(d),
-            CatchUpMovement::Diagonal(d1, d2) => {
-                tail.move_to_direction(d1);
-                tail.move_to_direction(d2);
-            }
+impl Position {
+    fn move_to_direction(&mut self, direction: &Direction) {
+        let (dx, dy) = direction.components();
+        self.x += dx;
+        self.y += dy;
+    }
+
+    /// A knot more than one step away from the knot it follows, diagonals included, moves one
+    /// step towards it on each axis where they differ
+    fn catch_up_with_head(&mut self, head: &Position) {
+        let (dx, dy) = (head.x - self.x, head.y - self.y);
+        if dx.abs().max(dy.abs()) > 1 {
+            self.x += dx.signum();
+            self.y += dy.signum();
         }
     }
 }
 
-impl Position {
-    fn move_to_direction(&mut self, direction: &Direction) {
-        match direction {
-            Direction::Up => self.y += 1,
-            Direction::Down => self.y -= 1,
-            Direction::Right => self.x += 1,
-            Direction::Left => self.x -= 1,
+/// Which knots of a rope have their visited positions recorded
+#[derive(Debug, Clone, Copy)]
+enum Track {
+    Knot(usize),
+    /// The last knot, whatever the length of the rope
+    Tail,
+    All,
+}
+
+/// Cells visited by a knot with the number of steps it ended on each of them, so memory
+/// grows with the distinct cells rather than with the number of steps
+#[derive(Debug, Default, Clone)]
+struct Visits {
+    counts: HashMap<Position, u32>,
+    steps: u64,
+    /// Bottom left and top right corners of the visited area
+    bounds: Option<(Position, Position)>,
+}
+
+impl Visits {
+    fn record(&mut self, position: Position) {
+        *self.counts.entry(position).or_default() += 1;
+        self.steps += 1;
+
+        self.bounds = Some(match self.bounds {
+            None => (position, position),
+            Some((min, max)) => (
+                Position::from((min.x.min(position.x), min.y.min(position.y))),
+                Position::from((max.x.max(position.x), max.y.max(position.y))),
+            ),
+        });
+    }
+
+    fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Cell the knot ended on the most often, the lowest one in `Position` order on ties
+    fn most_visited(&self) -> Option<(Position, u32)> {
+        self.counts
+            .iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+            .map(|(position, count)| (*position, *count))
+    }
+}
+
+/// Rope made of `knots.len()` knots, the first one being the head and the last one the tail
+#[derive(Debug)]
+struct Rope {
+    knots: Vec<Position>,
+    track: Track,
+    /// Cells visited by every knot, empty for the knots that are not tracked
+    visits: Vec<Visits>,
+}
+
+impl Rope {
+    /// Rope of `knots` knots starting at the origin and tracking its tail
+    fn new(knots: usize) -> Self {
+        Self::tracking(knots, Track::Tail)
+    }
+
+    fn tracking(knots: usize, track: Track) -> Self {
+        assert!(
+            knots >= 2,
+            "A rope needs at least 2 knots but {knots} were given"
+        );
+        if let Track::Knot(knot) = track {
+            assert!(
+                knot < knots,
+                "Can't track knot {knot} of a {knots} knots rope"
+            );
+        }
+
+        let mut rope = Self {
+            knots: vec![Position::from((0, 0)); knots],
+            track,
+            visits: vec![Visits::default(); knots],
         };
+        rope.record();
+        rope
     }
 
-    fn catch_up_with_head(&mut self, head: &Position) {
-        CatchUpMovement::from_head_tail(head, self).apply(self)
+    fn is_tracked(&self, knot: usize) -> bool {
+        match self.track {
+            Track::Knot(tracked) => tracked == knot,
+            Track::Tail => self.tail() == knot,
+            Track::All => true,
+        }
     }
 
-    fn move_with_tail(&mut self, tail: &mut Position, movement: &Movement) -> Vec<Position> {
-        let mut history = vec![];
+    fn record(&mut self) {
+        for knot in 0..self.knots.len() {
+            if self.is_tracked(knot) {
+                self.visits[knot].record(self.knots[knot]);
+            }
+        }
+    }
 
+    /// Moves the head one step towards `direction` and lets the other knots follow
+    fn step(&mut self, direction: &Direction) {
+        self.knots[0].move_to_direction(direction);
+        for i in 1..self.knots.len() {
+            let previous = self.knots[i - 1];
+            self.knots[i].catch_up_with_head(&previous);
+        }
+        self.record();
+    }
+
+    fn apply(&mut self, movement: &Movement) {
         for _ in 1..=movement.amount {
-            self.move_to_direction(&movement.direction);
-            tail.catch_up_with_head(self);
-            history.push(*tail);
+            self.step(&movement.direction);
         }
+    }
 
-        history
+    /// Label of a knot in the puzzle's drawings : `H` for the head, `T` for the tail of a two
+    /// knots rope and the knot index otherwise
+    fn label(&self, knot: usize) -> char {
+        match knot {
+            0 => 'H',
+            1 if self.knots.len() == 2 => 'T',
+            _ => std::char::from_digit(knot as u32, 36).unwrap_or('#'),
+        }
     }
-    fn move_with_tails(&mut self, tails: &mut [Position], movement: &Movement) -> Vec<Position> {
-        let mut history = vec![];
 
-        for _ in 1..=movement.amount {
-            self.move_to_direction(&movement.direction);
-            let mut previous = *self;
-            for i in 0..tails.len() {
-                tails[i].catch_up_with_head(&previous);
-                previous = tails[i];
+    /// Draws the rope within `(min, max)` the way the puzzle does, knots closer to the head
+    /// hiding the ones behind them
+    fn render(&self, (min, max): (Position, Position)) -> String {
+        let mut frame = String::new();
+        for y in (min.y..=max.y).rev() {
+            for x in min.x..=max.x {
+                let cell = Position::from((x, y));
+                let knot = self.knots.iter().position(|k| *k == cell);
+                frame.push(match knot {
+                    Some(knot) => self.label(knot),
+                    None if cell == Position::from((0, 0)) => 's',
+                    None => '.',
+                });
             }
-            history.push(tails[8]);
+            frame.push('\n');
         }
+        frame
+    }
+
+    fn tail(&self) -> usize {
+        self.knots.len() - 1
+    }
+
+    fn visits(&self, knot: usize) -> &Visits {
+        assert!(self.is_tracked(knot), "Knot {knot} is not tracked");
+        &self.visits[knot]
+    }
 
-        history
+    /// Number of distinct positions visited by a tracked `knot`
+    fn visited(&self, knot: usize) -> usize {
+        self.visits(knot).distinct()
+    }
+}
+
+/// Visited cells as `#` with the start as `s`, like the puzzle's drawings
+fn render_trail(visits: &Visits) -> String {
+    let Some((min, max)) = visits.bounds else {
+        return String::new();
+    };
+
+    let mut trail = String::new();
+    for y in (min.y..=max.y).rev() {
+        for x in min.x..=max.x {
+            let cell = Position::from((x, y));
+            trail.push(if cell == Position::from((0, 0)) {
+                's'
+            } else if visits.counts.contains_key(&cell) {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        trail.push('\n');
+    }
+    trail
+}
+
+type Rgb = [u8; 3];
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height],
+        }
+    }
+
+    fn fill(&mut self, (x, y): (usize, usize), (width, height): (usize, usize), color: Rgb) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                self.pixels[py * self.width + px] = color;
+            }
+        }
+    }
+
+    /// Binary PPM
+    fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut data = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in &self.pixels {
+            data.extend_from_slice(pixel);
+        }
+        std::fs::write(path, data)
+    }
+}
+
+/// Visited cells brighter the more often the knot ended on them, the start in red
+fn render_trail_image(visits: &Visits, scale: usize) -> Image {
+    let Some((min, max)) = visits.bounds else {
+        return Image::new(0, 0);
+    };
+
+    let width = (max.x - min.x + 1) as usize;
+    let height = (max.y - min.y + 1) as usize;
+    let mut image = Image::new(width * scale, height * scale);
+
+    let most = visits.most_visited().map_or(1, |(_, count)| count) as f64;
+    let pixel = |cell: &Position| {
+        let x = (cell.x - min.x) as usize * scale;
+        let y = (max.y - cell.y) as usize * scale;
+        (x, y)
+    };
+    for (cell, count) in &visits.counts {
+        // Logarithmic so that cells visited once stay visible next to the busiest ones
+        let t = (*count as f64).ln_1p() / most.ln_1p();
+        let grey = (80.0 + 175.0 * t) as u8;
+        image.fill(pixel(cell), (scale, scale), [grey, grey, grey]);
+    }
+    image.fill(pixel(&Position::from((0, 0))), (scale, scale), [255, 0, 0]);
+
+    image
+}
+
+/// Prints the whole rope after each step, the frame covering every cell the head goes through
+fn animate(input: &MovementList, knots: usize) {
+    let mut head = Rope::tracking(knots, Track::Knot(0));
+    for movement in input.movements() {
+        head.apply(movement);
+    }
+    let window = head.visits(0).bounds.expect("The start is always visited");
+
+    let mut rope = Rope::new(knots);
+    println!("== Initial State ==\n");
+    println!("{}", rope.render(window));
+    for movement in input.movements() {
+        println!("== {movement} ==\n");
+        for _ in 1..=movement.amount {
+            rope.step(&movement.direction);
+            println!("{}", rope.render(window));
+        }
     }
 }
 
@@ -175,39 +475,342 @@ impl From<(i64, i64)> for Position {
     }
 }
 
-fn part1(input: &MovementList) -> usize {
-    let mut head = Position::from((0, 0));
-    let mut tail = Position::from((0, 0));
-
-    let mut history = vec![tail];
-    for movement in &input.list {
-        let mut new_history = head.move_with_tail(&mut tail, movement);
-        history.append(&mut new_history);
+fn tail_visits(input: &MovementList, knots: usize) -> usize {
+    let mut rope = Rope::new(knots);
+    for movement in input.movements() {
+        rope.apply(movement);
     }
+    rope.visited(rope.tail())
+}
 
-    history.sort_unstable();
-    history.dedup();
-    history.len()
+fn part1(input: &MovementList) -> usize {
+    tail_visits(input, 2)
 }
 
 fn part2(input: &MovementList) -> usize {
-    let mut head = Position::from((0, 0));
-    let mut tails = vec![Position::from((0, 0)); 9];
+    tail_visits(input, 10)
+}
 
-    let mut history = vec![tails[8]];
-    for movement in &input.list {
-        let mut new_history = head.move_with_tails(&mut tails, movement);
-        history.append(&mut new_history);
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut knots = None;
+    let mut track = None;
+    let mut trail = false;
+    let mut image = None;
+    let mut scale = 4;
+    let mut animate_rope = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--knots" => {
+                knots = Some(
+                    args.next()
+                        .and_then(|k| k.parse().ok())
+                        .expect("--knots expects a number of knots"),
+                )
+            }
+            "--track" => {
+                track = Some(match args.next().as_deref() {
+                    Some("all") => Track::All,
+                    Some(knot) => Track::Knot(knot.parse().expect("--track expects a knot or all")),
+                    None => panic!("--track expects a knot or all"),
+                })
+            }
+            "--trail" => trail = true,
+            "--image" => image = Some(args.next().expect("--image expects an output path")),
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .expect("--scale expects a positive number of pixels per cell")
+            }
+            "--animate" => animate_rope = true,
+            _ if arg.starts_with("--") => panic!("Unexpected argument '{arg}'"),
+            _ => path = Some(arg),
+        }
     }
 
-    history.sort_unstable();
-    history.dedup();
-    history.len()
-}
+    let input = match path {
+        Some(path) => std::fs::read_to_string(path).expect("Failed to read movements"),
+        None => include_str!("day9.txt").to_string(),
+    };
+    let input: MovementList = input.parse().expect("Invalid movements");
 
-fn main() {
-    let input: MovementList = include_str!("day9.txt").parse().unwrap();
+    if animate_rope {
+        animate(&input, knots.unwrap_or(10));
+        return;
+    }
+
+    if knots.is_some() || track.is_some() || trail || image.is_some() {
+        let knots = knots.unwrap_or(10);
+        let track = track.unwrap_or(Track::Tail);
+        let mut rope = Rope::tracking(knots, track);
+        for movement in input.movements() {
+            rope.apply(movement);
+        }
+        for knot in (0..knots).filter(|k| rope.is_tracked(*k)) {
+            let visits = rope.visits(knot);
+            print!(
+                "knot {knot} : {} distinct cells over {} steps",
+                visits.distinct(),
+                visits.steps
+            );
+            if let Some((min, max)) = visits.bounds {
+                print!(", bounds ({}, {}) to ({}, {})", min.x, min.y, max.x, max.y);
+            }
+            if let Some((cell, count)) = visits.most_visited() {
+                print!(", most visited ({}, {}) {count} times", cell.x, cell.y);
+            }
+            println!();
+            if trail {
+                println!("{}", render_trail(visits));
+            }
+        }
+        if let Some(path) = image {
+            let knot = match track {
+                Track::Knot(knot) => knot,
+                Track::Tail => rope.tail(),
+                Track::All => panic!("--image needs a single tracked knot"),
+            };
+            render_trail_image(rope.visits(knot), scale)
+                .write(&path)
+                .expect("Failed to write the trail image");
+        }
+        return;
+    }
 
     println!("part 1 : {:?}", part1(&input));
     println!("part 2 : {:?}", part2(&input));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Knot-following rule the Chebyshev one replaced, kept as a reference
+    enum CatchUpMovement {
+        None,
+        One(Direction),
+        Diagonal(Direction, Direction),
+    }
+
+    impl CatchUpMovement {
+        fn from_head_tail(head: &Position, tail: &Position) -> Self {
+            if head.x == tail.x {
+                if head.y > tail.y + 1 {
+                    Self::One(Direction::Up)
+                } else if head.y < tail.y - 1 {
+                    Self::One(Direction::Down)
+                } else {
+                    Self::None
+                }
+            } else if head.y == tail.y {
+                if head.x > tail.x + 1 {
+                    Self::One(Direction::Right)
+                } else if head.x < tail.x - 1 {
+                    Self::One(Direction::Left)
+                } else {
+                    Self::None
+                }
+            } else if head.x > tail.x + 1 && head.y > tail.y
+                || head.x > tail.x && head.y > tail.y + 1
+            {
+                Self::Diagonal(Direction::Up, Direction::Right)
+            } else if head.x > tail.x + 1 && head.y < tail.y
+                || head.x > tail.x && head.y < tail.y - 1
+            {
+                Self::Diagonal(Direction::Down, Direction::Right)
+            } else if head.x < tail.x - 1 && head.y > tail.y
+                || head.x < tail.x && head.y > tail.y + 1
+            {
+                Self::Diagonal(Direction::Up, Direction::Left)
+            } else if head.x < tail.x - 1 && head.y < tail.y
+                || head.x < tail.x && head.y < tail.y - 1
+            {
+                Self::Diagonal(Direction::Down, Direction::Left)
+            } else {
+                Self::None
+            }
+        }
+
+        fn apply(&self, tail: &mut Position) {
+            match self {
+                CatchUpMovement::None => {}
+                CatchUpMovement::One(d) => tail.move_to_direction(d),
+                CatchUpMovement::Diagonal(d1, d2) => {
+                    tail.move_to_direction(d1);
+                    tail.move_to_direction(d2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn catch_up_matches_the_previous_rule() {
+        for dx in -2..=2 {
+            for dy in -2..=2 {
+                let head = Position::from((dx, dy));
+                let mut expected = Position::from((0, 0));
+                CatchUpMovement::from_head_tail(&head, &expected).apply(&mut expected);
+
+                let mut tail = Position::from((0, 0));
+                tail.catch_up_with_head(&head);
+                assert_eq!(tail, expected, "head at ({dx}, {dy})");
+            }
+        }
+    }
+
+    fn movements(script: &str) -> Vec<String> {
+        let list: MovementList = script.parse().unwrap();
+        list.movements().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn inline_and_multi_line_blocks_agree() {
+        let inline = movements("repeat 2 { U 1; repeat 2 { DR 1 } } L 3 # back");
+        let multi_line = movements("repeat 2 {\n  U 1\n  repeat 2 {\n    DR 1\n  }\n}\nL 3\n");
+        assert_eq!(
+            inline,
+            ["U 1", "DR 1", "DR 1", "U 1", "DR 1", "DR 1", "L 3"]
+        );
+        assert_eq!(inline, multi_line);
+    }
+
+    #[test]
+    fn nested_repeats_are_expanded_lazily() {
+        let list: MovementList = "repeat 4000000000 { repeat 4000000000 { R 1 } }"
+            .parse()
+            .unwrap();
+        assert_eq!(list.movements().take(3).count(), 3);
+    }
+
+    #[test]
+    fn malformed_scripts_are_rejected() {
+        for (script, error) in [
+            ("R 1\nX 2", "line 2 : Invalid direction"),
+            ("R", "line 1 : No space separator"),
+            ("R two", "line 1 : Invalid amount 'two'"),
+            ("repeat 2\nR 1", "line 1 : expected '{'"),
+            ("repeat x { R 1 }", "line 1 : invalid repeat count 'x'"),
+            ("repeat 2 {\nR 1", "line 1 : repeat block is never closed"),
+            ("R 1\n}", "line 2 : '}' without an open repeat block"),
+        ] {
+            let result = script.parse::<MovementList>();
+            let message = result.err().map(|e| e.to_string()).unwrap_or_default();
+            assert!(message.starts_with(error), "{script:?} gave {message:?}");
+        }
+    }
+
+    const EXAMPLE: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+    const LARGER_EXAMPLE: &str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+
+    #[test]
+    fn puzzle_examples() {
+        let example: MovementList = EXAMPLE.parse().unwrap();
+        assert_eq!(part1(&example), 13);
+        assert_eq!(part2(&example), 1);
+
+        let larger: MovementList = LARGER_EXAMPLE.parse().unwrap();
+        assert_eq!(part2(&larger), 36);
+    }
+
+    #[test]
+    #[should_panic(expected = "A rope needs at least 2 knots but 0 were given")]
+    fn rope_without_knots() {
+        Rope::new(0);
+    }
+
+    #[test]
+    fn visit_statistics() {
+        let example: MovementList = EXAMPLE.parse().unwrap();
+        let mut rope = Rope::tracking(2, Track::All);
+        for movement in example.movements() {
+            rope.apply(movement);
+        }
+
+        let head = rope.visits(0);
+        assert_eq!(head.distinct(), 21);
+        assert_eq!(head.steps, 25);
+        assert_eq!(
+            head.bounds,
+            Some((Position::from((0, 0)), Position::from((5, 4))))
+        );
+        // (1, 2), (2, 2), (4, 2) and (4, 3) are all visited twice
+        assert_eq!(head.most_visited(), Some((Position::from((1, 2)), 2)));
+
+        let tail = rope.visits(1);
+        assert_eq!(tail.distinct(), 13);
+        assert_eq!(tail.steps, 25);
+        assert_eq!(
+            tail.bounds,
+            Some((Position::from((0, 0)), Position::from((4, 4))))
+        );
+        assert_eq!(tail.most_visited(), Some((Position::from((4, 3)), 6)));
+
+        let tail_only = Rope::new(2);
+        assert!(tail_only.is_tracked(1) && !tail_only.is_tracked(0));
+        assert_eq!(tail_only.visits[0].steps, 0);
+    }
+
+    #[test]
+    fn trail_of_the_larger_example() {
+        let larger: MovementList = LARGER_EXAMPLE.parse().unwrap();
+        let mut rope = Rope::new(10);
+        for movement in larger.movements() {
+            rope.apply(movement);
+        }
+
+        assert_eq!(
+            render_trail(rope.visits(rope.tail())),
+            "\
+#.....................
+#.............###.....
+#............#...#....
+.#..........#.....#...
+..#..........#.....#..
+...#........#.......#.
+....#......s.........#
+.....#..............#.
+......#............#..
+.......#..........#...
+........#........#....
+.........########.....
+"
+        );
+    }
+
+    #[test]
+    fn rope_frames() {
+        let window = (Position::from((0, 0)), Position::from((5, 4)));
+        let mut rope = Rope::new(2);
+        assert_eq!(
+            rope.render(window),
+            "......\n......\n......\n......\nH.....\n"
+        );
+        rope.step(&Direction::Right);
+        assert_eq!(
+            rope.render(window),
+            "......\n......\n......\n......\nTH....\n"
+        );
+        rope.step(&Direction::Right);
+        rope.step(&Direction::Right);
+        assert_eq!(
+            rope.render(window),
+            "......\n......\n......\n......\ns.TH..\n"
+        );
+
+        // The larger example after `R 5`, knots 5 to 9 still on the start
+        let window = (Position::from((-11, -5)), Position::from((14, 15)));
+        let mut rope = Rope::new(10);
+        rope.apply(&"R 5".parse().unwrap());
+        let frame = rope.render(window);
+        let rows = frame.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 21);
+        assert_eq!(rows[15], "...........54321H.........");
+        assert!(rows
+            .iter()
+            .enumerate()
+            .all(|(y, row)| y == 15 || row.chars().all(|c| c == '.')));
+    }
+}